@@ -0,0 +1,279 @@
+use anyhow::Result;
+use chrono::Local;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use crate::config::get_config_dir;
+use crate::metadata::audio_variant;
+use crate::probe;
+use crate::quality::quality_label;
+use crate::types::{LinkInfo, Settings};
+
+/// Query parameters that sign or expire a URL rather than say what it points to.
+const SIGNING_PARAMS: [&str; 6] = ["token", "expires", "signature", "policy", "key-pair-id", "hdnts"];
+/// Short keys that only sign a URL next to another signing key, as in `?st=…&e=…` or
+/// `?exp=…&sig=…`. Alone they may well be part of the address, e.g. `watch?id=x&e=3`.
+const SHORT_SIGNING_PARAMS: [&str; 5] = ["e", "st", "exp", "sig", "md5"];
+/// Files a rebuild looks at.
+const MEDIA_EXTENSIONS: [&str; 5] = ["mkv", "mp4", "m4v", "webm", "ts"];
+
+/// A finished download. It matches a link by normalized source URL, or by show, season,
+/// episode, quality and dub or audio languages when those are known, so the file may since
+/// have been renamed or moved.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub url: String,
+    pub identity: Option<String>,
+    pub path: PathBuf,
+    pub hash: String,
+    pub date: String,
+}
+
+pub struct Archive {
+    entries: Vec<Entry>,
+}
+
+fn archive_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("archive.jsonl"))
+}
+
+/// Drops the fragment and signing parameters, which change between otherwise identical links.
+pub fn normalize_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url.trim()) else { return url.trim().to_string() };
+    parsed.set_fragment(None);
+    let keys: Vec<String> = parsed.query_pairs().map(|(key, _)| key.to_lowercase()).collect();
+    let signing = |key: &str| SIGNING_PARAMS.contains(&key) || key.starts_with("x-amz-");
+    let short = |key: &str| SHORT_SIGNING_PARAMS.contains(&key);
+    let signed = keys.iter().any(|k| signing(k)) || keys.iter().filter(|k| short(k)).count() > 1;
+    let query: Vec<(String, String)> = parsed.query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !(signing(&key) || signed && short(&key))
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+    parsed.to_string()
+}
+
+/// Season defaults to 1 like the `season_number` tag, so tagged files give the same identity back.
+fn identity(show: Option<&str>, season: Option<u32>, episode: Option<u32>, quality: Option<&str>, audio: Option<&str>) -> Option<String> {
+    let show = show?.trim().to_lowercase();
+    let mut identity = format!("{}|s{}|e{}|{}", show, season.unwrap_or(1), episode?, quality.map(quality_label).unwrap_or_default().to_lowercase());
+    if let Some(audio) = audio.filter(|a| !a.is_empty()) {
+        identity.push_str(&format!("|{}", audio.to_lowercase()));
+    }
+    Some(identity)
+}
+
+fn link_identity(link_info: &LinkInfo) -> Option<String> {
+    identity(link_info.show.as_deref(), link_info.season, link_info.episode, link_info.quality.as_deref(), audio_variant(link_info).as_deref())
+}
+
+/// FNV-1a over the file's contents, enough to tell files apart.
+fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; 1 << 20];
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buf[..read] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    Ok(format!("fnv1a64:{:016x}", hash))
+}
+
+fn entry_to_json(entry: &Entry) -> String {
+    json!({
+        "url": entry.url,
+        "identity": entry.identity,
+        "path": entry.path.to_string_lossy(),
+        "hash": entry.hash,
+        "date": entry.date,
+    })
+    .to_string()
+}
+
+fn entry_from_json(line: &str) -> Option<Entry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    Some(Entry {
+        url: value["url"].as_str()?.to_string(),
+        identity: value["identity"].as_str().map(String::from),
+        path: PathBuf::from(value["path"].as_str()?),
+        hash: value["hash"].as_str().unwrap_or_default().to_string(),
+        date: value["date"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+fn new_entry(url: &str, identity: Option<String>, file: &Path) -> Result<Entry> {
+    Ok(Entry {
+        url: normalize_url(url),
+        identity,
+        path: fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf()),
+        hash: hash_file(file)?,
+        date: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+    })
+}
+
+impl Archive {
+    /// Reads the archive, skipping lines it can't make sense of.
+    pub fn load() -> Result<Self> {
+        let content = fs::read_to_string(archive_path()?).unwrap_or_default();
+        Ok(Archive { entries: content.lines().filter_map(entry_from_json).collect() })
+    }
+
+    pub fn find(&self, link_info: &LinkInfo) -> Option<&Entry> {
+        let url = normalize_url(&link_info.url);
+        let identity = link_identity(link_info);
+        self.entries.iter().rev().find(|e| e.url == url || (identity.is_some() && e.identity == identity))
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(archive_path()?, self.entries.iter().map(|e| entry_to_json(e) + "\n").collect::<String>())?;
+        Ok(())
+    }
+}
+
+/// Adds a finished download to the archive. Lines are appended so parallel downloads can
+/// record at the same time.
+pub fn record(link_info: &LinkInfo, file: &Path) -> Result<()> {
+    let entry = new_entry(&link_info.url, link_identity(link_info), file)?;
+    let mut archive = OpenOptions::new().create(true).append(true).open(archive_path()?)?;
+    archive.write_all((entry_to_json(&entry) + "\n").as_bytes())?;
+    Ok(())
+}
+
+fn media_files(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for path in entries.flatten().map(|e| e.path()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            media_files(&path, found);
+        } else if !name.contains(".anilink-")
+            && path.extension().and_then(|e| e.to_str()).is_some_and(|e| MEDIA_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        {
+            found.push(path);
+        }
+    }
+}
+
+/// Drops entries whose files are gone, then adds files in `folders` and next to the remaining
+/// entries that carry the `source_url` tag ffmpeg writes. Direct downloads that needed no muxing
+/// never went through ffmpeg and have no tags. Returns how many entries were kept and added,
+/// and how many files were skipped for lack of the tag.
+pub async fn rebuild(settings: &Settings, folders: &[PathBuf]) -> Result<(usize, usize, usize)> {
+    let mut archive = Archive::load()?;
+    archive.entries.retain(|e| e.path.exists());
+    let kept = archive.entries.len();
+
+    let mut dirs: Vec<PathBuf> = folders.to_vec();
+    dirs.extend(archive.entries.iter().filter_map(|e| e.path.parent().map(Path::to_path_buf)));
+    dirs.sort();
+    dirs.dedup();
+    let mut files = Vec::new();
+    for dir in &dirs {
+        media_files(dir, &mut files);
+    }
+    files.sort();
+    files.dedup();
+
+    let mut untagged = 0;
+    for file in files {
+        let path = fs::canonicalize(&file).unwrap_or_else(|_| file.clone());
+        if archive.entries.iter().any(|e| e.path == path) {
+            continue;
+        }
+        let url = probe::ffprobe(settings, &file.to_string_lossy(), None).await.ok()
+            .and_then(|info| Some((info.tags.get("source_url")?.clone(), info)));
+        let Some((url, info)) = url else {
+            untagged += 1;
+            continue;
+        };
+        let number = |key: &str| info.tags.get(key).and_then(|v| v.parse().ok());
+        let tag = |key: &str| info.tags.get(key).map(String::as_str);
+        let identity = identity(tag("show"), number("season_number"), number("episode_sort"), tag("quality"), tag("audio_variant"));
+        // Hashing reads the whole video
+        if let Ok(Ok(entry)) = tokio::task::spawn_blocking(move || new_entry(&url, identity, &file)).await {
+            archive.entries.push(entry);
+        }
+    }
+
+    archive.save()?;
+    Ok((kept, archive.entries.len() - kept, untagged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::new_link;
+    use crate::types::AudioTrack;
+
+    #[test]
+    fn urls_lose_signing_but_keep_what_tells_episodes_apart() {
+        let cases = [
+            ("https://cdn.example.com/ep3.m3u8?token=abc&expires=123#t=10", "https://cdn.example.com/ep3.m3u8"),
+            ("https://cdn.example.com/ep3.mp4?X-Amz-Signature=abc&X-Amz-Date=1&v=2", "https://cdn.example.com/ep3.mp4?v=2"),
+            ("https://cdn.example.com/ep3.m3u8?st=abc&e=123", "https://cdn.example.com/ep3.m3u8"),
+            ("https://cdn.example.com/ep3.m3u8?exp=123&sig=abc&id=7", "https://cdn.example.com/ep3.m3u8?id=7"),
+            ("https://cdn.example.com/ep3.m3u8?hdnts=abc&e=9", "https://cdn.example.com/ep3.m3u8"),
+            ("https://example.com/watch?id=x&e=3", "https://example.com/watch?id=x&e=3"),
+            ("https://example.com/watch?id=x&e=4", "https://example.com/watch?id=x&e=4"),
+            ("  not a url  ", "not a url"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(normalize_url(url), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn identity_tells_dubs_and_extra_audio_apart() {
+        let mut sub = new_link(0, "Frieren - 03", "https://example.com/a.m3u8".to_string());
+        sub.show = Some("Frieren".to_string());
+        sub.episode = Some(3);
+        sub.quality = Some("[1080p]".to_string());
+        assert_eq!(link_identity(&sub).as_deref(), Some("frieren|s1|e3|1080p"));
+
+        let mut dub = sub.clone();
+        dub.name = "Frieren - 03 (Dub)".to_string();
+        assert_eq!(link_identity(&dub).as_deref(), Some("frieren|s1|e3|1080p|dub"));
+
+        let mut dual = sub.clone();
+        dual.audio.push(AudioTrack { name: "English".to_string(), url: "https://example.com/en.m3u8".to_string(), default: false, language: None });
+        assert_eq!(link_identity(&dual).as_deref(), Some("frieren|s1|e3|1080p|eng"));
+
+        // Files read back through their tags give the identity they were recorded with
+        assert_eq!(identity(Some(" Frieren "), None, Some(3), Some("1080p"), Some("dub")), link_identity(&dub));
+        assert_eq!(identity(Some("Frieren"), Some(1), Some(3), Some("1080p"), None), link_identity(&sub));
+        assert_eq!(identity(None, Some(1), Some(3), None, None), None);
+        assert_eq!(identity(Some("Frieren"), Some(1), None, None, None), None);
+    }
+
+    #[test]
+    fn entries_survive_a_json_round_trip() {
+        let entry = Entry {
+            url: "https://example.com/a.m3u8".to_string(),
+            identity: Some("frieren|s1|e3|1080p".to_string()),
+            path: PathBuf::from("/videos/Frieren/Frieren - 03.mkv"),
+            hash: "fnv1a64:0123456789abcdef".to_string(),
+            date: "2026-10-19 12:00".to_string(),
+        };
+        let read = entry_from_json(&entry_to_json(&entry)).unwrap();
+        assert_eq!((&read.url, &read.identity, &read.path, &read.hash, &read.date), (&entry.url, &entry.identity, &entry.path, &entry.hash, &entry.date));
+
+        let bare = entry_from_json(r#"{"url":"https://example.com/b.mp4","path":"/videos/b.mp4"}"#).unwrap();
+        assert_eq!((bare.identity, bare.hash, bare.date), (None, String::new(), String::new()));
+        assert!(entry_from_json(r#"{"path":"/videos/b.mp4"}"#).is_none());
+        assert!(entry_from_json("not json").is_none());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::types::Chapter;
+
+fn parse_time(s: &str) -> Option<f64> {
+    let parts: Vec<f64> = s.trim().split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    match parts.as_slice() {
+        [s] => Some(*s),
+        [m, s] => Some(m * 60.0 + s),
+        [h, m, s] => Some(h * 3600.0 + m * 60.0 + s),
+        _ => None,
+    }
+}
+
+/// Parses a chapter list such as `Intro=1:30-3:00,Outro=22:10-23:40`.
+/// Times are seconds or `[hh:]mm:ss`, invalid items are ignored.
+pub fn parse_chapters(s: &str) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = s
+        .split(',')
+        .filter_map(|item| {
+            let (title, range) = item.split_once('=')?;
+            let (start, end) = range.split_once('-')?;
+            let (start, end) = (parse_time(start)?, parse_time(end)?);
+            (end > start).then(|| Chapter { title: title.trim().to_string(), start, end })
+        })
+        .collect();
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    chapters
+}
+
+fn escape(value: &str) -> String {
+    value.chars().fold(String::new(), |mut out, c| {
+        if "=;#\\\n".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
+
+/// Builds an ffmetadata file for the chapters, filling the gaps between markers with "Episode"
+/// chapters so players can skip straight past an opening. The tail is only covered when the
+/// duration is known.
+pub fn to_ffmetadata(chapters: &[Chapter], duration: Option<f64>) -> String {
+    let mut timeline: Vec<Chapter> = Vec::new();
+    let mut cursor = 0.0;
+    for chapter in chapters {
+        if chapter.start > cursor + 1.0 {
+            timeline.push(Chapter { title: "Episode".to_string(), start: cursor, end: chapter.start });
+        }
+        let start = chapter.start.max(cursor);
+        if chapter.end > start {
+            timeline.push(Chapter { title: chapter.title.clone(), start, end: chapter.end });
+            cursor = chapter.end;
+        }
+    }
+    if let Some(duration) = duration.filter(|d| *d > cursor + 1.0) {
+        timeline.push(Chapter { title: "Episode".to_string(), start: cursor, end: duration });
+    }
+
+    let mut out = String::from(";FFMETADATA1\n");
+    for chapter in timeline {
+        out += &format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start * 1000.0) as u64,
+            (chapter.end * 1000.0) as u64,
+            escape(&chapter.title)
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(chapters: &[Chapter]) -> Vec<(&str, f64, f64)> {
+        chapters.iter().map(|c| (c.title.as_str(), c.start, c.end)).collect()
+    }
+
+    #[test]
+    fn chapter_lists_parse_sorted_and_skip_bad_items() {
+        let chapters = parse_chapters("Outro=22:10-23:40, Intro = 1:30-3:00,Recap=0:00:05-45,Broken=3:00,Backwards=5-4,Bad=a-b");
+        assert_eq!(spans(&chapters), [("Recap", 5.0, 45.0), ("Intro", 90.0, 180.0), ("Outro", 1330.0, 1420.0)]);
+        assert!(parse_chapters("").is_empty());
+    }
+
+    #[test]
+    fn ffmetadata_fills_gaps_and_escapes_titles() {
+        let chapters = parse_chapters("Intro=90-180,Recap=170-200,Outro=1330-1420");
+        assert_eq!(
+            to_ffmetadata(&chapters, Some(1440.0)),
+            ";FFMETADATA1\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=90000\ntitle=Episode\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=90000\nEND=180000\ntitle=Intro\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=180000\nEND=200000\ntitle=Recap\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=200000\nEND=1330000\ntitle=Episode\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=1330000\nEND=1420000\ntitle=Outro\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=1420000\nEND=1440000\ntitle=Episode\n"
+        );
+
+        // Without a duration the tail stays uncovered
+        let intro = [Chapter { title: "OP; part=1 #a".to_string(), start: 0.0, end: 90.0 }];
+        assert_eq!(
+            to_ffmetadata(&intro, None),
+            ";FFMETADATA1\n\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=90000\ntitle=OP\\; part\\=1 \\#a\n"
+        );
+    }
+}
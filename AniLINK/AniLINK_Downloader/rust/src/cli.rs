@@ -0,0 +1,52 @@
+use clap::Parser;
+use std::str::FromStr;
+use crate::types::QualityPreference;
+
+#[derive(Parser, Debug, Clone)]
+#[command(name = "anilink_downloader", version, about = "M3U Batch Downloader for AniLINK")]
+pub struct Cli {
+    /// Playlists to download: files, folders of playlists, wildcards such as "*.m3u", http(s) URLs or - for stdin
+    pub playlist: Vec<String>,
+
+    /// Referer to send when fetching a playlist URL
+    #[arg(long)]
+    pub referer: Option<String>,
+
+    /// Which source to keep when an episode appears several times: "best", "all" or a quality such as 1080p
+    #[arg(short, long)]
+    pub quality: Option<QualityPreference>,
+
+    /// Transcode profile to use for this run instead of copying streams ("copy" to force copying)
+    #[arg(short, long)]
+    pub profile: Option<String>,
+
+    /// List each entry with its output path and effective request headers, then exit without downloading
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Probe every entry for reachability, stream type, duration and size, then exit without downloading
+    #[arg(long)]
+    pub check: bool,
+
+    /// Download entries even if the archive says they were downloaded before
+    #[arg(long)]
+    pub ignore_archive: bool,
+
+    /// Rebuild the download archive from the files in the output folders before downloading
+    #[arg(long)]
+    pub rebuild_archive: bool,
+}
+
+impl FromStr for QualityPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_start_matches('[').trim_end_matches(']').trim();
+        match s.to_lowercase().as_str() {
+            "" => Err("quality cannot be empty".to_string()),
+            "best" => Ok(QualityPreference::Best),
+            "all" => Ok(QualityPreference::All),
+            _ => Ok(QualityPreference::Specific(s.to_string())),
+        }
+    }
+}
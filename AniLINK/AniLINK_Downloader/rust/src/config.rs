@@ -2,7 +2,9 @@ use anyhow::{anyhow, Result};
 use ini::Ini;
 use std::fs;
 use std::path::{Path, PathBuf};
-use crate::types::Settings;
+use crate::naming::DEFAULT_TEMPLATE;
+use crate::transcode::{builtin_profiles, COPY_PROFILE};
+use crate::types::{Container, Settings, SubtitleFormat, TranscodeProfile};
 
 pub fn get_config_dir() -> Result<PathBuf> {
     let path = if cfg!(windows) {
@@ -39,6 +41,81 @@ pub fn load_settings(conf: &Ini) -> Settings {
         .and_then(|s| s.get("ffmpeg_path"))
         .map(|v| v.to_string())
         .unwrap_or_else(|| "ffmpeg".to_string());
+    let name_template = section
+        .and_then(|s| s.get("name_template"))
+        .and_then(|v| if v.is_empty() { None } else { Some(v.to_string()) })
+        .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+    let season_folders = section
+        .and_then(|s| s.get("season_folders"))
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let write_nfo = section
+        .and_then(|s| s.get("write_nfo"))
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let container = section
+        .and_then(|s| s.get("container"))
+        .and_then(|v| v.parse::<Container>().ok())
+        .unwrap_or(Container::Mkv);
+    let transcode_profile = section
+        .and_then(|s| s.get("transcode_profile"))
+        .and_then(|v| if v.is_empty() || v == COPY_PROFILE { None } else { Some(v.to_string()) });
+
+    let sidecar_subtitles = section
+        .and_then(|s| s.get("sidecar_subtitles"))
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let sidecar_format = section
+        .and_then(|s| s.get("sidecar_format"))
+        .and_then(|v| v.parse::<SubtitleFormat>().ok());
+    let fonts_dir = section
+        .and_then(|s| s.get("fonts_dir"))
+        .and_then(|v| if v.is_empty() { None } else { Some(v.to_string()) });
+    let direct_downloads = section
+        .and_then(|s| s.get("direct_downloads"))
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let connections = section
+        .and_then(|s| s.get("connections"))
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(4)
+        .max(1);
+    let host_connections: Vec<(String, usize)> = conf
+        .section(Some("Connections"))
+        .map(|props| props.iter().filter_map(|(host, v)| Some((host.to_lowercase(), v.parse::<usize>().ok()?.max(1)))).collect())
+        .unwrap_or_default();
+    let probe_links = section
+        .and_then(|s| s.get("probe_links"))
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let min_free_space_mb = section
+        .and_then(|s| s.get("min_free_space_mb"))
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    let verify_downloads = section
+        .and_then(|s| s.get("verify_downloads"))
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    let mut profiles: Vec<TranscodeProfile> = conf
+        .iter()
+        .filter_map(|(name, props)| {
+            let name = name?.strip_prefix("Profile.")?;
+            Some(TranscodeProfile {
+                name: name.to_string(),
+                video_codec: props.get("video_codec").unwrap_or("libx264").to_string(),
+                crf: props.get("crf").and_then(|v| v.parse().ok()),
+                video_bitrate: props.get("video_bitrate").filter(|v| !v.is_empty()).map(String::from),
+                max_height: props.get("max_height").and_then(|v| v.parse().ok()),
+                preset: props.get("preset").filter(|v| !v.is_empty()).map(String::from),
+                audio_codec: props.get("audio_codec").unwrap_or("aac").to_string(),
+                audio_bitrate: props.get("audio_bitrate").filter(|v| !v.is_empty()).map(String::from),
+            })
+        })
+        .collect();
+    if profiles.is_empty() {
+        profiles = builtin_profiles();
+    }
 
     Settings {
         parallel_downloads,
@@ -46,17 +123,74 @@ pub fn load_settings(conf: &Ini) -> Settings {
         speed_limit,
         timeout,
         ffmpeg_path,
+        name_template,
+        season_folders,
+        write_nfo,
+        container,
+        transcode_profile,
+        profiles,
+        sidecar_subtitles,
+        sidecar_format,
+        fonts_dir,
+        direct_downloads,
+        connections,
+        host_connections,
+        probe_links,
+        min_free_space_mb,
+        verify_downloads,
     }
 }
 
-pub fn save_settings(config_file: &Path, settings: &Settings) -> Result<()> {
+fn settings_to_ini(settings: &Settings) -> Ini {
     let mut conf = Ini::new();
     conf.with_section(Some("Settings"))
         .set("parallel_downloads", settings.parallel_downloads.to_string())
         .set("retries", settings.retries.to_string())
         .set("speed_limit", settings.speed_limit.as_deref().unwrap_or(""))
         .set("timeout", settings.timeout.to_string())
-        .set("ffmpeg_path", &settings.ffmpeg_path);
-    conf.write_to_file(config_file)?;
+        .set("ffmpeg_path", &settings.ffmpeg_path)
+        .set("name_template", &settings.name_template)
+        .set("season_folders", settings.season_folders.to_string())
+        .set("write_nfo", settings.write_nfo.to_string())
+        .set("container", settings.container.to_string())
+        .set("transcode_profile", settings.transcode_profile.as_deref().unwrap_or(COPY_PROFILE))
+        .set("sidecar_subtitles", settings.sidecar_subtitles.to_string())
+        .set("sidecar_format", settings.sidecar_format.map(|f| f.to_string()).unwrap_or_else(|| "original".to_string()))
+        .set("fonts_dir", settings.fonts_dir.as_deref().unwrap_or(""))
+        .set("direct_downloads", settings.direct_downloads.to_string())
+        .set("connections", settings.connections.to_string())
+        .set("probe_links", settings.probe_links.to_string())
+        .set("min_free_space_mb", settings.min_free_space_mb.to_string())
+        .set("verify_downloads", settings.verify_downloads.to_string());
+    for (host, connections) in &settings.host_connections {
+        conf.with_section(Some("Connections")).set(host, connections.to_string());
+    }
+    for profile in &settings.profiles {
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        conf.with_section(Some(format!("Profile.{}", profile.name)))
+            .set("video_codec", &profile.video_codec)
+            .set("crf", opt(profile.crf.map(|v| v.to_string())))
+            .set("video_bitrate", opt(profile.video_bitrate.clone()))
+            .set("max_height", opt(profile.max_height.map(|v| v.to_string())))
+            .set("preset", opt(profile.preset.clone()))
+            .set("audio_codec", &profile.audio_codec)
+            .set("audio_bitrate", opt(profile.audio_bitrate.clone()));
+    }
+    conf
+}
+
+pub fn save_settings(config_file: &Path, settings: &Settings) -> Result<()> {
+    settings_to_ini(settings).write_to_file(config_file)?;
     Ok(())
 }
+
+/// Layers the keys set in an overrides file (same layout as settings.ini) on top of `settings`.
+pub fn apply_overrides(settings: &Settings, overrides: &Ini) -> Settings {
+    let mut conf = settings_to_ini(settings);
+    for (section, props) in overrides.iter() {
+        for (key, value) in props.iter() {
+            conf.with_section(section).set(key, value);
+        }
+    }
+    load_settings(&conf)
+}
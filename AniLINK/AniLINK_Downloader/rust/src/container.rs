@@ -0,0 +1,102 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::types::{Container, LinkInfo};
+use crate::utils::url_extension;
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "mkv" => Ok(Container::Mkv),
+            "mp4" => Ok(Container::Mp4),
+            "ts" => Ok(Container::Ts),
+            "original" => Ok(Container::Original),
+            other => Err(format!("unknown container '{}' (expected mkv, mp4, ts or original)", other)),
+        }
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Container::Mkv => "mkv",
+            Container::Mp4 => "mp4",
+            Container::Ts => "ts",
+            Container::Original => "original",
+        })
+    }
+}
+
+/// File extension written for a link. "original" keeps progressive files as they are and
+/// stores HLS streams as MPEG-TS, which is what their segments usually are.
+pub fn output_extension(container: Container, url: &str) -> &'static str {
+    match container {
+        Container::Mkv => "mkv",
+        Container::Mp4 => "mp4",
+        Container::Ts => "ts",
+        Container::Original => match url_extension(url).as_deref() {
+            Some("mp4") | Some("m4v") | Some("mpd") => "mp4",
+            Some("webm") => "webm",
+            Some("m3u8") | Some("ts") => "ts",
+            _ => "mkv",
+        },
+    }
+}
+
+pub fn supports_subtitles(ext: &str) -> bool {
+    ext != "ts"
+}
+
+fn is_ass(url: &str) -> bool {
+    matches!(url_extension(url).as_deref(), Some("ass") | Some("ssa"))
+}
+
+/// Whether a link's audio comes as AAC in ADTS frames, which is how HLS and MPEG-TS carry it.
+/// Progressive files and DASH already store AAC the way MP4 does, or use other codecs.
+pub fn has_adts_audio(url: &str) -> bool {
+    matches!(url_extension(url).as_deref(), Some("m3u8") | Some("ts"))
+}
+
+/// Output options ffmpeg needs so that copied streams fit the chosen container.
+/// `copies_adts_audio` is set when ADTS AAC audio is copied rather than re-encoded.
+pub fn ffmpeg_args(ext: &str, has_subtitles: bool, copies_adts_audio: bool) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    match ext {
+        "mp4" => {
+            if has_subtitles {
+                args.extend(["-c:s".into(), "mov_text".into()]);
+            }
+            if copies_adts_audio {
+                // MP4 can't store ADTS frames as-is, and the filter rejects any other codec
+                args.extend(["-bsf:a".into(), "aac_adtstoasc".into()]);
+            }
+            // use_metadata_tags keeps our custom tags, which MP4 would otherwise drop
+            args.extend(["-movflags".into(), "+faststart+use_metadata_tags".into()]);
+        }
+        "webm" if has_subtitles => {
+            args.extend(["-c:s".into(), "webvtt".into()]);
+        }
+        _ => {}
+    }
+    args
+}
+
+/// Lists problems with storing the selected tracks in the chosen container. With sidecar
+/// subtitles on, subtitles the container can't hold are still written next to the file.
+pub fn validate(container: Container, sidecar_subtitles: bool, links: &[LinkInfo]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for link in links.iter().filter(|l| !l.subtitles.is_empty()) {
+        match output_extension(container, &link.url) {
+            "ts" if sidecar_subtitles => {
+                warnings.push(format!("{}: MPEG-TS can't hold text subtitles, {} track(s) will only be written next to the file", link.name, link.subtitles.len()));
+            }
+            "ts" => warnings.push(format!("{}: MPEG-TS can't hold text subtitles, {} track(s) will be dropped", link.name, link.subtitles.len())),
+            "mp4" if link.subtitles.iter().any(|s| is_ass(&s.url)) => {
+                warnings.push(format!("{}: ASS subtitles will be converted to mov_text and lose their styling", link.name));
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
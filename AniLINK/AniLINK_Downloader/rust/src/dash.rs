@@ -0,0 +1,256 @@
+use reqwest::Client;
+use roxmltree::{Document, Node};
+use crate::hls::{resolve_url, variant_rank};
+use crate::http;
+use crate::types::{LinkInfo, Subtitle};
+use crate::utils::url_extension;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentKind {
+    Video,
+    Audio,
+    Text,
+}
+
+#[derive(Debug, Clone)]
+pub struct Representation {
+    pub kind: ContentKind,
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub language: Option<String>,
+    pub label: Option<String>,
+    /// The adaptation set is marked with the "main" role.
+    pub main: bool,
+    pub mime_type: String,
+    pub codecs: String,
+    /// URL of the whole representation when it isn't split into segments, as text tracks usually are.
+    pub url: Option<String>,
+}
+
+/// A stream to keep, by its position among the manifest's representations of its type.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub index: usize,
+    pub language: Option<String>,
+    pub label: Option<String>,
+}
+
+/// Representations picked from a manifest. ffmpeg exposes every representation as its own
+/// stream, in manifest order per type, so indexes map straight to `0:v:N`, `0:a:N` and `0:s:N`.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub video: Option<usize>,
+    pub audio: Vec<Track>,
+    /// Single-file text tracks, fetched and checked like any other subtitle.
+    pub subtitles: Vec<Subtitle>,
+    /// Segmented WebVTT tracks, read by ffmpeg along with the video.
+    pub text: Vec<Track>,
+    /// Names of segmented text tracks in formats that can't be muxed, such as TTML.
+    pub skipped_text: Vec<String>,
+}
+
+pub fn is_dash(url: &str) -> bool {
+    url_extension(url).as_deref() == Some("mpd")
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+/// Applies a level's `<BaseURL>` on top of the one inherited from its parent.
+fn base_url(node: Node, parent: &str) -> String {
+    match child(node, "BaseURL").and_then(|b| b.text()).map(str::trim) {
+        Some(url) if !url.is_empty() => resolve_url(parent, url),
+        _ => parent.to_string(),
+    }
+}
+
+fn kind(mime_type: &str, content_type: Option<&str>, codecs: &str) -> Option<ContentKind> {
+    match content_type.unwrap_or_else(|| mime_type.split('/').next().unwrap_or_default()) {
+        "video" => Some(ContentKind::Video),
+        "audio" => Some(ContentKind::Audio),
+        "text" => Some(ContentKind::Text),
+        _ if mime_type.contains("ttml") || codecs.contains("wvtt") || codecs.contains("stpp") => Some(ContentKind::Text),
+        _ => None,
+    }
+}
+
+/// Reads the representations of the first period. Other periods are usually ads or recaps
+/// and ffmpeg doesn't read them either.
+pub fn parse_manifest(content: &str, manifest_url: &str) -> Option<Vec<Representation>> {
+    let doc = Document::parse(content).ok()?;
+    let mpd = doc.root_element();
+    if !mpd.has_tag_name("MPD") {
+        return None;
+    }
+    let mpd_base = base_url(mpd, manifest_url);
+    let period = child(mpd, "Period")?;
+    let period_base = base_url(period, &mpd_base);
+
+    let mut representations = Vec::new();
+    for set in period.children().filter(|n| n.has_tag_name("AdaptationSet")) {
+        let set_base = base_url(set, &period_base);
+        let main = set.children().any(|n| n.has_tag_name("Role") && n.attribute("value") == Some("main"));
+        let label = child(set, "Label").and_then(|l| l.text()).or(set.attribute("label")).map(String::from);
+        for rep in set.children().filter(|n| n.has_tag_name("Representation")) {
+            // Most attributes may sit on either level, the representation's win
+            let attr = |name: &str| rep.attribute(name).or_else(|| set.attribute(name));
+            let Some(kind) = kind(attr("mimeType").unwrap_or_default(), set.attribute("contentType"), attr("codecs").unwrap_or_default()) else { continue };
+            let segmented = ["SegmentTemplate", "SegmentList"].iter().any(|s| child(rep, s).is_some() || child(set, s).is_some());
+            let has_base = child(rep, "BaseURL").is_some() || child(set, "BaseURL").is_some();
+            representations.push(Representation {
+                kind,
+                bandwidth: attr("bandwidth").and_then(|v| v.parse().ok()).unwrap_or(0),
+                resolution: attr("width").zip(attr("height")).and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?))),
+                language: attr("lang").map(String::from),
+                label: label.clone(),
+                main,
+                mime_type: attr("mimeType").unwrap_or_default().to_string(),
+                codecs: attr("codecs").unwrap_or_default().to_string(),
+                url: (!segmented && has_base).then(|| base_url(rep, &set_base)),
+            });
+        }
+    }
+    Some(representations)
+}
+
+/// Reads `mediaPresentationDuration`, an ISO 8601 duration such as `PT1H23M4.5S`.
+pub fn presentation_duration(content: &str) -> Option<f64> {
+    let doc = Document::parse(content).ok()?;
+    let value = doc.root_element().attribute("mediaPresentationDuration")?.strip_prefix('P')?;
+    let (days, time) = value.split_once('T').unwrap_or((value, ""));
+    let mut seconds = days.strip_suffix('D').and_then(|d| d.parse::<f64>().ok()).unwrap_or(0.0) * 86400.0;
+    let mut number = String::new();
+    for c in time.chars() {
+        match c {
+            'H' | 'M' | 'S' => {
+                let unit = match c { 'H' => 3600.0, 'M' => 60.0, _ => 1.0 };
+                seconds += number.parse::<f64>().ok()? * unit;
+                number.clear();
+            }
+            _ => number.push(c),
+        }
+    }
+    Some(seconds)
+}
+
+/// Applies the HLS rules: the highest resolution video, bandwidth breaking ties. Audio keeps
+/// the best representation per language with the main one first. Text tracks are all kept,
+/// single files as subtitles and segmented WebVTT as streams of the manifest.
+pub fn select(representations: &[Representation]) -> Selection {
+    let of_kind = |kind| representations.iter().filter(move |r| r.kind == kind).enumerate();
+    let video = of_kind(ContentKind::Video).max_by_key(|(_, r)| variant_rank(r.resolution, r.bandwidth)).map(|(i, _)| i);
+
+    let mut audio: Vec<(usize, &Representation)> = Vec::new();
+    for (i, rep) in of_kind(ContentKind::Audio) {
+        match audio.iter_mut().find(|(_, kept)| kept.language == rep.language) {
+            Some(kept) if rep.bandwidth > kept.1.bandwidth => *kept = (i, rep),
+            Some(_) => {}
+            None => audio.push((i, rep)),
+        }
+    }
+    audio.sort_by_key(|(_, rep)| !rep.main);
+
+    let mut selection = Selection {
+        video,
+        audio: audio.into_iter().map(|(index, rep)| Track { index, language: rep.language.clone(), label: rep.label.clone() }).collect(),
+        ..Selection::default()
+    };
+    for (index, rep) in of_kind(ContentKind::Text) {
+        let name = rep.label.clone().or_else(|| rep.language.clone()).unwrap_or_else(|| "Subtitle".to_string());
+        match &rep.url {
+            Some(url) => selection.subtitles.push(Subtitle { name, url: url.clone(), default: false, language: rep.language.clone() }),
+            // Matroska and mov_text take WebVTT, TTML has neither a decoder nor a place in them
+            None if rep.codecs.contains("wvtt") || rep.mime_type == "text/vtt" => {
+                selection.text.push(Track { index, language: rep.language.clone(), label: Some(name) });
+            }
+            None => selection.skipped_text.push(name),
+        }
+    }
+    selection
+}
+
+/// Fetches and reads a link's manifest. `None` when it isn't DASH or can't be read, in which
+/// case ffmpeg is left to pick streams itself.
+pub async fn resolve(client: &Client, link_info: &LinkInfo, retries: u32) -> Option<Selection> {
+    if !is_dash(&link_info.url) {
+        return None;
+    }
+    let content = http::get_text(client, &link_info.url, link_info, retries).await.ok()?;
+    Some(select(&parse_manifest(&content, &link_info.url)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" mediaPresentationDuration="PT1H2M3.5S">
+  <BaseURL>media/</BaseURL>
+  <Period>
+    <AdaptationSet contentType="video" mimeType="video/mp4">
+      <SegmentTemplate media="v$Number$.m4s"/>
+      <Representation id="v1" bandwidth="800000" width="1280" height="720"/>
+      <Representation id="v2" bandwidth="3000000" width="1920" height="1080"/>
+      <Representation id="v3" bandwidth="2000000" width="1920" height="1080"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4" lang="en" label="English">
+      <Representation id="a1" bandwidth="64000"/>
+      <Representation id="a2" bandwidth="128000"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4" lang="ja">
+      <Role schemeIdUri="urn:mpeg:dash:role:2011" value="main"/>
+      <Representation id="a3" bandwidth="128000"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="text/vtt" lang="en">
+      <Representation id="t1" bandwidth="1000"><BaseURL>subs/en.vtt</BaseURL></Representation>
+    </AdaptationSet>
+    <AdaptationSet mimeType="application/mp4" codecs="wvtt" lang="es" label="Español">
+      <SegmentTemplate media="t$Number$.m4s"/>
+      <Representation id="t2" bandwidth="1000"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="application/mp4" codecs="stpp" lang="fr">
+      <SegmentTemplate media="f$Number$.m4s"/>
+      <Representation id="t3" bandwidth="1000"/>
+    </AdaptationSet>
+  </Period>
+  <Period><AdaptationSet mimeType="video/mp4"><Representation bandwidth="1"/></AdaptationSet></Period>
+</MPD>"#;
+
+    #[test]
+    fn manifests_give_representations_of_the_first_period() {
+        let reps = parse_manifest(MANIFEST, "https://cdn/show/ep1.mpd").unwrap();
+        let kinds: Vec<_> = reps.iter().map(|r| r.kind).collect();
+        use ContentKind::*;
+        assert_eq!(kinds, [Video, Video, Video, Audio, Audio, Audio, Text, Text, Text]);
+        assert_eq!(reps[1].resolution, Some((1920, 1080)));
+        assert_eq!(reps[3].language.as_deref(), Some("en"));
+        assert_eq!(reps[3].label.as_deref(), Some("English"));
+        assert!(reps[5].main);
+        assert_eq!(reps[6].url.as_deref(), Some("https://cdn/show/media/subs/en.vtt"));
+        assert_eq!(reps[7].url, None);
+        assert!(parse_manifest("<MPD/>", "https://cdn/a.mpd").is_none());
+        assert!(parse_manifest("#EXTM3U", "https://cdn/a.mpd").is_none());
+    }
+
+    #[test]
+    fn selection_follows_the_hls_rules_and_keeps_text_tracks() {
+        let selection = select(&parse_manifest(MANIFEST, "https://cdn/show/ep1.mpd").unwrap());
+        assert_eq!(selection.video, Some(1));
+        let audio: Vec<_> = selection.audio.iter().map(|a| (a.index, a.language.as_deref())).collect();
+        assert_eq!(audio, [(2, Some("ja")), (1, Some("en"))]);
+        assert_eq!(selection.subtitles.len(), 1);
+        assert_eq!(selection.subtitles[0].url, "https://cdn/show/media/subs/en.vtt");
+        let text: Vec<_> = selection.text.iter().map(|t| (t.index, t.label.as_deref())).collect();
+        assert_eq!(text, [(1, Some("Español"))]);
+        assert_eq!(selection.skipped_text, ["fr"]);
+    }
+
+    #[test]
+    fn presentation_durations_are_read_as_seconds() {
+        assert_eq!(presentation_duration(MANIFEST), Some(3723.5));
+        assert_eq!(presentation_duration(r#"<MPD mediaPresentationDuration="P1DT0.5S"/>"#), Some(86400.5));
+        assert_eq!(presentation_duration(r#"<MPD mediaPresentationDuration="PT24M"/>"#), Some(1440.0));
+        assert_eq!(presentation_duration("<MPD/>"), None);
+    }
+}
@@ -0,0 +1,437 @@
+use anyhow::{anyhow, bail, Result};
+use futures_util::{Stream, StreamExt};
+use reqwest::header::{HeaderMap, ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, StatusCode, Url};
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use crate::http;
+use crate::types::{LinkInfo, Settings};
+use crate::utils::{parse_rate, url_extension};
+
+/// Progressive files that can be fetched as they are, without ffmpeg.
+const DIRECT_EXTENSIONS: [&str; 5] = ["mp4", "m4v", "mkv", "webm", "mov"];
+/// Smallest range worth its own connection.
+const MIN_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+pub struct Progress {
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    pub bytes_per_sec: f64,
+}
+
+/// A byte range of the file, `end` exclusive, of which the first `done` bytes are written.
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    start: u64,
+    end: u64,
+    done: u64,
+}
+
+/// What every connection of a chunked download shares.
+struct ChunkJob {
+    client: Client,
+    link_info: LinkInfo,
+    part: PathBuf,
+    validator: Option<String>,
+    timeout: u64,
+    retries: u32,
+    limit: Option<u64>,
+    progress: Vec<AtomicU64>,
+}
+
+pub fn is_direct(url: &str) -> bool {
+    url.starts_with("http") && url_extension(url).is_some_and(|ext| DIRECT_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Where a direct download is kept until it's complete, so an interrupted one can be resumed.
+pub fn part_path(output_file: &Path, url: &str) -> PathBuf {
+    let stem = output_file.file_stem().unwrap_or_default().to_string_lossy();
+    output_file.with_file_name(format!("{}.anilink-part.{}", stem, url_extension(url).unwrap_or_default()))
+}
+
+/// Files kept next to the part file: `etag` holds the ETag or Last-Modified of the file being
+/// resumed, `chunks` the progress of each range of a chunked download.
+fn sidecar_path(part: &Path, suffix: &str) -> PathBuf {
+    let mut path = part.as_os_str().to_owned();
+    path.push(format!(".{}", suffix));
+    PathBuf::from(path)
+}
+
+pub fn remove_part(part: &Path) {
+    let _ = fs::remove_file(part);
+    let _ = fs::remove_file(sidecar_path(part, "etag"));
+    let _ = fs::remove_file(sidecar_path(part, "chunks"));
+}
+
+/// Connections to open for a URL: the [Connections] entry for its host or closest parent domain,
+/// otherwise the default.
+pub fn connections_for(settings: &Settings, url: &str) -> usize {
+    let host = Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_lowercase)).unwrap_or_default();
+    settings.host_connections.iter()
+        .filter(|(h, _)| host == *h || host.ends_with(&format!(".{}", h)))
+        .max_by_key(|(h, _)| h.len())
+        .map(|(_, n)| *n)
+        .unwrap_or(settings.connections)
+}
+
+/// Weak ETags can't be used with If-Range, Last-Modified is the fallback.
+fn validator(headers: &HeaderMap) -> Option<&str> {
+    headers.get(ETAG).and_then(|v| v.to_str().ok()).filter(|v| !v.starts_with("W/"))
+        .or_else(|| headers.get(LAST_MODIFIED).and_then(|v| v.to_str().ok()))
+}
+
+fn stored_validator(part: &Path) -> Option<String> {
+    fs::read_to_string(sidecar_path(part, "etag")).ok().filter(|v| !v.is_empty())
+}
+
+/// Total size from a `Content-Range: bytes 0-99/1000` header.
+fn content_range_total(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_RANGE)?.to_str().ok()?.rsplit('/').next()?.parse().ok()
+}
+
+fn split(total: u64, connections: usize) -> Vec<Chunk> {
+    let count = (connections as u64).min(total / MIN_CHUNK_SIZE).max(1);
+    let size = total.div_ceil(count);
+    (0..count)
+        .map(|i| Chunk { start: i * size, end: ((i + 1) * size).min(total), done: 0 })
+        .collect()
+}
+
+fn load_chunks(path: &Path) -> Option<Vec<Chunk>> {
+    let chunks: Vec<Chunk> = fs::read_to_string(path).ok()?
+        .lines()
+        .map(|line| {
+            let mut values = line.split_whitespace().map(|v| v.parse::<u64>().ok());
+            Some(Chunk { start: values.next()??, end: values.next()??, done: values.next()?? })
+        })
+        .collect::<Option<_>>()?;
+    (!chunks.is_empty()).then_some(chunks)
+}
+
+fn save_chunks(path: &Path, chunks: &[Chunk]) -> Result<()> {
+    fs::write(path, chunks.iter().map(|c| format!("{} {} {}\n", c.start, c.end, c.done)).collect::<String>())?;
+    Ok(())
+}
+
+async fn wait_while_paused(link_info: &LinkInfo) {
+    // Reading stops while paused, if the server drops the connection meanwhile the retry resumes it
+    while link_info.paused.load(Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+async fn next_bytes<S, B>(stream: &mut S, timeout: u64) -> Result<Option<B>>
+where
+    S: Stream<Item = reqwest::Result<B>> + Unpin,
+{
+    match tokio::time::timeout(Duration::from_secs(timeout), stream.next()).await {
+        Ok(next) => Ok(next.transpose()?),
+        Err(_) => bail!("No data received for {} seconds", timeout),
+    }
+}
+
+/// Sleeps long enough to keep `fetched` bytes since `start` under the speed limit.
+async fn throttle(start: Instant, fetched: u64, limit: Option<u64>) {
+    if let Some(limit) = limit {
+        let ahead = fetched as f64 / limit as f64 - start.elapsed().as_secs_f64();
+        if ahead > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(ahead)).await;
+        }
+    }
+}
+
+fn rate(start: Instant, fetched: u64) -> f64 {
+    let elapsed = start.elapsed().as_secs_f64();
+    if elapsed > 0.0 { fetched as f64 / elapsed } else { 0.0 }
+}
+
+/// Downloads a link into its part file, resuming after failed attempts and earlier runs.
+/// Returns the size of the finished file.
+pub async fn download(client: &Client, link_info: &LinkInfo, part: &Path, settings: &Settings, mut on_progress: impl FnMut(Progress)) -> Result<u64> {
+    let mut attempt = 1;
+    loop {
+        match fetch(client, link_info, part, settings, &mut on_progress).await {
+            Ok(size) => return Ok(size),
+            Err(e) if attempt >= settings.retries.max(1) => return Err(e),
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+/// Checks with a one-byte request whether the server still has the file a part was started from:
+/// by its ETag or Last-Modified when it sent one, otherwise by the size the saved ranges add up to.
+async fn unchanged(client: &Client, link_info: &LinkInfo, part: &Path, total: u64) -> Result<bool> {
+    let validator = stored_validator(part);
+    let mut request = http::with_headers(client.get(&link_info.url), link_info).header(RANGE, "bytes=0-0");
+    if let Some(validator) = &validator {
+        request = request.header(IF_RANGE, validator);
+    }
+    let resp = request.send().await?.error_for_status()?;
+    Ok(resp.status() == StatusCode::PARTIAL_CONTENT
+        && (validator.is_some() || content_range_total(resp.headers()) == Some(total)))
+}
+
+/// Continues from what's already in the part file. If-Range makes the server send the whole
+/// file instead when it changed since, in which case the part file starts over. Large files
+/// from servers that accept ranges are handed to `fetch_chunked`.
+async fn fetch(client: &Client, link_info: &LinkInfo, part: &Path, settings: &Settings, on_progress: &mut impl FnMut(Progress)) -> Result<u64> {
+    let state = sidecar_path(part, "chunks");
+    if state.exists() {
+        let total = load_chunks(&state).and_then(|chunks| chunks.iter().map(|c| c.end).max());
+        if let Some(total) = total {
+            if unchanged(client, link_info, part, total).await? {
+                return fetch_chunked(client, link_info, part, settings, on_progress, None).await;
+            }
+        }
+        remove_part(part);
+    }
+
+    let existing = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let mut request = http::with_headers(client.get(&link_info.url), link_info);
+    if existing > 0 {
+        request = request.header(RANGE, format!("bytes={}-", existing));
+        if let Some(validator) = stored_validator(part) {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+    let resp = request.send().await?;
+
+    if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE && existing > 0 {
+        // Nothing left past the end of the part file
+        if content_range_total(resp.headers()).is_none_or(|total| total == existing) {
+            return Ok(existing);
+        }
+        remove_part(part);
+        bail!("Partial download doesn't match the file on the server");
+    }
+    let resp = resp.error_for_status()?;
+    let resumed = resp.status() == StatusCode::PARTIAL_CONTENT;
+    let total = if resumed { content_range_total(resp.headers()) } else { resp.content_length() };
+    if let Some(validator) = validator(resp.headers()) {
+        fs::write(sidecar_path(part, "etag"), validator)?;
+    }
+
+    let accepts_ranges = resp.headers().get(ACCEPT_RANGES).is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"bytes"));
+    if let Some(total) = total.filter(|&t| !resumed && accepts_ranges && split(t, connections_for(settings, &link_info.url)).len() > 1) {
+        drop(resp);
+        return fetch_chunked(client, link_info, part, settings, on_progress, Some(total)).await;
+    }
+
+    let mut file = if resumed { OpenOptions::new().append(true).open(part)? } else { fs::File::create(part)? };
+    let mut downloaded = if resumed { existing } else { 0 };
+    let limit = settings.speed_limit.as_deref().and_then(parse_rate);
+    let start = Instant::now();
+    let mut fetched: u64 = 0;
+    let mut stream = resp.bytes_stream();
+
+    loop {
+        wait_while_paused(link_info).await;
+        let Some(bytes) = next_bytes(&mut stream, settings.timeout).await? else { break };
+        file.write_all(&bytes)?;
+        downloaded += bytes.len() as u64;
+        fetched += bytes.len() as u64;
+        throttle(start, fetched, limit).await;
+        on_progress(Progress { downloaded, total, bytes_per_sec: rate(start, fetched) });
+    }
+    file.flush()?;
+
+    match total {
+        Some(total) if downloaded != total => bail!("Download ended after {} of {} bytes", downloaded, total),
+        _ => Ok(downloaded),
+    }
+}
+
+/// Fetches the file as several ranges in parallel, written in place into a sparse part file.
+/// Each range retries on its own; their progress is saved so a later attempt only fetches
+/// what's missing. Starts a new split when `total` is given, resumes the saved one otherwise.
+async fn fetch_chunked(client: &Client, link_info: &LinkInfo, part: &Path, settings: &Settings, on_progress: &mut impl FnMut(Progress), total: Option<u64>) -> Result<u64> {
+    let state = sidecar_path(part, "chunks");
+    let chunks = match total {
+        Some(total) => {
+            let chunks = split(total, connections_for(settings, &link_info.url));
+            fs::File::create(part)?.set_len(total)?;
+            save_chunks(&state, &chunks)?;
+            chunks
+        }
+        None => match load_chunks(&state) {
+            Some(chunks) => chunks,
+            None => {
+                remove_part(part);
+                bail!("Chunked download state is unreadable, starting over");
+            }
+        },
+    };
+    let total = chunks.iter().map(|c| c.end).max().unwrap_or(0);
+
+    let job = Arc::new(ChunkJob {
+        client: client.clone(),
+        link_info: link_info.clone(),
+        part: part.to_path_buf(),
+        validator: stored_validator(part),
+        timeout: settings.timeout,
+        retries: settings.retries,
+        // The speed limit applies to the whole download, so each connection gets its share
+        limit: settings.speed_limit.as_deref().and_then(parse_rate).map(|l| (l / chunks.len() as u64).max(1)),
+        progress: chunks.iter().map(|c| AtomicU64::new(c.done)).collect(),
+    });
+    let snapshot = |job: &ChunkJob| -> Vec<Chunk> {
+        chunks.iter().zip(&job.progress).map(|(c, done)| Chunk { done: done.load(Ordering::SeqCst), ..*c }).collect()
+    };
+
+    let mut tasks = JoinSet::new();
+    for (index, chunk) in chunks.iter().enumerate().filter(|(_, c)| c.start + c.done < c.end) {
+        tasks.spawn(fetch_chunk(job.clone(), index, *chunk));
+    }
+
+    let resumed_from: u64 = chunks.iter().map(|c| c.done).sum();
+    let start = Instant::now();
+    let mut ticker = tokio::time::interval(Duration::from_millis(500));
+    let result = loop {
+        tokio::select! {
+            next = tasks.join_next() => match next {
+                None => break Ok(()),
+                Some(Ok(Ok(()))) => {}
+                Some(Ok(Err(e))) => break Err(e),
+                Some(Err(e)) => break Err(anyhow!(e)),
+            },
+            _ = ticker.tick() => {
+                let current = snapshot(&job);
+                let downloaded: u64 = current.iter().map(|c| c.done).sum();
+                on_progress(Progress { downloaded, total: Some(total), bytes_per_sec: rate(start, downloaded - resumed_from) });
+                save_chunks(&state, &current)?;
+            }
+        }
+    };
+    tasks.abort_all();
+    save_chunks(&state, &snapshot(&job))?;
+    result?;
+
+    on_progress(Progress { downloaded: total, total: Some(total), bytes_per_sec: rate(start, total - resumed_from) });
+    fs::remove_file(&state)?;
+    Ok(total)
+}
+
+async fn fetch_chunk(job: Arc<ChunkJob>, index: usize, chunk: Chunk) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match fetch_range(&job, index, chunk).await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= job.retries.max(1) => return Err(e.context(format!("Range {}-{} failed", chunk.start, chunk.end - 1))),
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+/// Fetches what's missing of one range, continuing where the previous try stopped.
+async fn fetch_range(job: &ChunkJob, index: usize, chunk: Chunk) -> Result<()> {
+    let mut offset = chunk.start + job.progress[index].load(Ordering::SeqCst);
+    if offset >= chunk.end {
+        return Ok(());
+    }
+    let mut request = http::with_headers(job.client.get(&job.link_info.url), &job.link_info)
+        .header(RANGE, format!("bytes={}-{}", offset, chunk.end - 1));
+    if let Some(validator) = &job.validator {
+        request = request.header(IF_RANGE, validator);
+    }
+    let resp = request.send().await?.error_for_status()?;
+    if resp.status() != StatusCode::PARTIAL_CONTENT {
+        bail!("Server sent the whole file instead of the requested range");
+    }
+
+    let mut file = OpenOptions::new().write(true).open(&job.part)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let start = Instant::now();
+    let mut fetched: u64 = 0;
+    let mut stream = resp.bytes_stream();
+
+    while offset < chunk.end {
+        wait_while_paused(&job.link_info).await;
+        let Some(bytes) = next_bytes(&mut stream, job.timeout).await? else { break };
+        // Anything past the end of the range belongs to the next one
+        let take = bytes.len().min((chunk.end - offset) as usize);
+        file.write_all(&bytes[..take])?;
+        offset += take as u64;
+        fetched += take as u64;
+        job.progress[index].fetch_add(take as u64, Ordering::SeqCst);
+        throttle(start, fetched, job.limit).await;
+    }
+    file.flush()?;
+
+    if offset < chunk.end {
+        bail!("Connection closed {} bytes before the end of the range", chunk.end - offset);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ini::Ini;
+
+    #[test]
+    fn split_covers_the_file_in_contiguous_chunks() {
+        let small = split(MIN_CHUNK_SIZE, 8);
+        assert_eq!(small.len(), 1);
+        assert_eq!((small[0].start, small[0].end), (0, MIN_CHUNK_SIZE));
+
+        let total = 10 * MIN_CHUNK_SIZE + 3;
+        let chunks = split(total, 4);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0].start, 0);
+        assert_eq!(chunks.last().unwrap().end, total);
+        assert!(chunks.windows(2).all(|pair| pair[0].end == pair[1].start));
+        assert!(chunks.iter().all(|c| c.done == 0 && c.end > c.start));
+
+        // Never more chunks than MIN_CHUNK_SIZE allows
+        assert_eq!(split(3 * MIN_CHUNK_SIZE, 16).len(), 3);
+    }
+
+    #[test]
+    fn chunk_progress_survives_a_save_and_load() {
+        let path = std::env::temp_dir().join(format!("anilink-chunks-test-{}", std::process::id()));
+        let chunks = [Chunk { start: 0, end: 100, done: 40 }, Chunk { start: 100, end: 250, done: 150 }];
+        save_chunks(&path, &chunks).unwrap();
+        let loaded = load_chunks(&path).unwrap();
+        assert_eq!(
+            loaded.iter().map(|c| (c.start, c.end, c.done)).collect::<Vec<_>>(),
+            vec![(0, 100, 40), (100, 250, 150)]
+        );
+
+        fs::write(&path, "0 100 40\ngarbage\n").unwrap();
+        assert!(load_chunks(&path).is_none());
+        fs::write(&path, "").unwrap();
+        assert!(load_chunks(&path).is_none());
+        let _ = fs::remove_file(&path);
+        assert!(load_chunks(&path).is_none());
+    }
+
+    #[test]
+    fn connections_use_the_closest_matching_domain() {
+        let mut settings = crate::config::load_settings(&Ini::new());
+        settings.connections = 4;
+        settings.host_connections = vec![("example.com".to_string(), 2), ("cdn.example.com".to_string(), 8)];
+        let cases = [
+            ("https://example.com/a.mp4", 2),
+            ("https://www.example.com/a.mp4", 2),
+            ("https://edge.cdn.example.com/a.mp4", 8),
+            ("https://CDN.Example.com/a.mp4", 8),
+            ("https://notexample.com/a.mp4", 4),
+            ("not a url", 4),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(connections_for(&settings, url), expected, "{}", url);
+        }
+    }
+}
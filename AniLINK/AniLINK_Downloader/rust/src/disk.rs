@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MB: u64 = 1_048_576;
+
+/// A filesystem without room for what's queued on it.
+pub struct Shortage {
+    pub folders: Vec<PathBuf>,
+    pub needed: u64,
+    pub free: u64,
+}
+
+/// Output folders may not exist yet, so their nearest existing parent stands in for them.
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors().find(|p| !p.as_os_str().is_empty() && p.exists()).unwrap_or(Path::new("."))
+}
+
+/// Free bytes on the filesystem holding `path`.
+pub fn free_space(path: &Path) -> Option<u64> {
+    available(existing_ancestor(path))
+}
+
+#[cfg(unix)]
+fn available(path: &Path) -> Option<u64> {
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    Some(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+#[cfg(windows)]
+fn available(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    use winapi::um::winnt::ULARGE_INTEGER;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    unsafe {
+        let mut free: ULARGE_INTEGER = std::mem::zeroed();
+        if GetDiskFreeSpaceExW(wide.as_ptr(), &mut free, std::ptr::null_mut(), std::ptr::null_mut()) == 0 {
+            return None;
+        }
+        Some(*free.QuadPart())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Identifies the filesystem a folder lives on, so folders sharing one are checked together.
+fn filesystem_id(path: &Path) -> String {
+    let path = existing_ancestor(path);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(metadata) = fs::metadata(path) {
+            return metadata.dev().to_string();
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        // The drive or share prefix
+        if let Some(prefix) = fs::canonicalize(path).ok().and_then(|p| p.components().next().map(|c| c.as_os_str().to_string_lossy().to_string())) {
+            return prefix;
+        }
+    }
+    path.to_string_lossy().to_string()
+}
+
+/// Whether new downloads into `folder` should wait for space to be freed.
+pub fn low_space(folder: &Path, threshold: u64) -> bool {
+    threshold > 0 && free_space(folder).is_some_and(|free| free < threshold)
+}
+
+/// Compares the estimated size of what's queued for each folder, plus the threshold that
+/// should stay free, with the free space of the filesystem it's on.
+pub fn check_space(estimates: &[(PathBuf, u64)], threshold: u64) -> Vec<Shortage> {
+    let mut filesystems: HashMap<String, (Vec<PathBuf>, u64)> = HashMap::new();
+    for (folder, size) in estimates {
+        let entry = filesystems.entry(filesystem_id(folder)).or_default();
+        entry.0.push(folder.clone());
+        entry.1 += size;
+    }
+    filesystems.into_values()
+        .filter_map(|(folders, size)| {
+            let free = free_space(&folders[0])?;
+            let needed = size + threshold;
+            (needed > free).then_some(Shortage { folders, needed, free })
+        })
+        .collect()
+}
+
+/// What the TUI shows while any of the folders is below the threshold.
+pub fn low_space_notice(folders: &[PathBuf], threshold: u64) -> Option<String> {
+    let low: Vec<String> = folders.iter()
+        .filter_map(|folder| {
+            let free = free_space(folder).filter(|&free| threshold > 0 && free < threshold)?;
+            Some(format!("{} MB free for {}", free / MB, folder.display()))
+        })
+        .collect();
+    (!low.is_empty()).then(|| format!("Low disk space: {}. New downloads wait until more than {} MB are free.", low.join(", "), threshold / MB))
+}
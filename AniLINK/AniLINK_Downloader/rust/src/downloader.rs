@@ -4,21 +4,199 @@ use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, BufReader as AsyncBufReader};
 use tokio::process::Command;
 use std::process::Stdio;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
-use crate::types::{LinkInfo, Settings, DownloadStatus};
-use crate::parser::{parse_ffmpeg_duration, parse_ffmpeg_time};
-use crate::utils::get_output_file;
+use crate::types::{AppError, LinkInfo, Settings, DownloadStatus, Speed, Subtitle, TrackStatus};
+use crate::parser::{parse_ffmpeg_duration, parse_ffmpeg_stream, parse_ffmpeg_time};
+use crate::utils::url_extension;
+use crate::naming;
+use crate::nfo;
+use crate::container;
+use crate::transcode;
+use crate::http;
+use crate::hls::{self, ResolvedInput};
+use crate::subtitles;
+use crate::lang;
+use crate::fonts;
+use crate::chapters;
+use crate::metadata;
+use crate::dash;
+use crate::direct;
+use crate::probe;
+use crate::archive;
+use crate::verify::{self, StreamCounts};
+
+/// Fetches and checks every subtitle before muxing. Valid tracks are written next to the video,
+/// as sidecars when configured or as temporary files otherwise. Tracks that can't be fetched or
+/// repaired are reported and left out instead of failing the episode.
+async fn prepare_subtitles(client: &reqwest::Client, link_info: &LinkInfo, output_file: &Path, settings: &Settings) -> Vec<(Subtitle, PathBuf)> {
+    let mut prepared: Vec<(Subtitle, PathBuf)> = Vec::new();
+    let stem = output_file.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    link_info.subtitle_status.lock().clear();
+
+    for (i, sub) in link_info.subtitles.iter().enumerate() {
+        let checked = http::get_text_with_type(client, &sub.url, link_info, settings.retries).await
+            .map_err(|e| e.to_string())
+            .and_then(|(body, content_type)| subtitles::validate(&sub.url, content_type.as_deref(), &body));
+        let checked = match checked {
+            Ok(checked) => checked,
+            Err(reason) => {
+                link_info.warnings.lock().push(format!("Subtitle '{}' skipped: {}", sub.name, reason));
+                link_info.subtitle_status.lock().push((sub.name.clone(), TrackStatus::Skipped { reason }));
+                continue;
+            }
+        };
+
+        let (path, content) = if settings.sidecar_subtitles {
+            let format = settings.sidecar_format.unwrap_or(checked.format);
+            let taken: Vec<PathBuf> = prepared.iter().map(|(_, p)| p.clone()).collect();
+            (subtitles::sidecar_path(output_file, sub, format, &taken), subtitles::convert(&checked.content, checked.format, format))
+        } else {
+            (output_file.with_file_name(format!("{}.anilink-sub{}.{}", stem, i, checked.format.extension())), checked.content)
+        };
+        if let Err(e) = fs::write(&path, content) {
+            let reason = e.to_string();
+            link_info.warnings.lock().push(format!("Subtitle '{}' skipped: {}", sub.name, reason));
+            link_info.subtitle_status.lock().push((sub.name.clone(), TrackStatus::Skipped { reason }));
+            continue;
+        }
+        link_info.subtitle_status.lock().push((sub.name.clone(), TrackStatus::Ready { repairs: checked.repairs }));
+        prepared.push((sub.clone(), path));
+    }
+    prepared
+}
+
+/// Finds the fonts referenced by muxed ASS tracks in the configured fonts directory so players
+/// can render them as intended. Fonts that can't be found are reported as warnings.
+fn collect_fonts(link_info: &LinkInfo, subtitle_inputs: &[(Subtitle, PathBuf)], settings: &Settings) -> Vec<PathBuf> {
+    let Some(fonts_dir) = settings.fonts_dir.as_deref().map(Path::new) else { return Vec::new() };
+    let mut attachments: Vec<PathBuf> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+    for (_, path) in subtitle_inputs {
+        let is_ass = path.extension().and_then(|e| e.to_str()).is_some_and(|e| e == "ass" || e == "ssa");
+        let Some(script) = is_ass.then(|| fs::read_to_string(path).ok()).flatten() else { continue };
+        for family in fonts::referenced_fonts(&script) {
+            match fonts::find_font(fonts_dir, &family) {
+                Some(font) if !attachments.contains(&font) => attachments.push(font),
+                Some(_) => {}
+                None if !missing.contains(&family) => missing.push(family),
+                None => {}
+            }
+        }
+    }
+    if !missing.is_empty() {
+        link_info.warnings.lock().push(format!("Fonts not found in {}: {}", fonts_dir.display(), missing.join(", ")));
+    }
+    attachments
+}
+
+async fn fetch_cover(client: &reqwest::Client, link_info: &LinkInfo, logo: &str, output_file: &Path, settings: &Settings) -> Option<(PathBuf, &'static str)> {
+    match http::get_with_type(client, logo, link_info, settings.retries).await {
+        Ok((body, content_type)) => {
+            let (mimetype, ext) = metadata::image_mimetype(content_type.as_deref(), logo);
+            let path = output_file.with_file_name(format!("{}.anilink-cover.{}", output_file.file_stem().unwrap_or_default().to_string_lossy(), ext));
+            fs::write(&path, body).ok()?;
+            Some((path, mimetype))
+        }
+        Err(e) => {
+            link_info.warnings.lock().push(format!("Cover image skipped: {}", e));
+            None
+        }
+    }
+}
+
+pub fn add_input(cmd: &mut Command, input: &str, link_info: &LinkInfo) {
+    if input.starts_with("http") {
+        let (user_agent, headers): (Vec<_>, Vec<_>) = http::request_headers(link_info)
+            .into_iter()
+            .partition(|(name, _)| name.eq_ignore_ascii_case("User-Agent"));
+        // ffmpeg sends its own User-Agent, so one passed in -headers would be duplicated
+        if let Some((_, user_agent)) = user_agent.last() {
+            cmd.arg("-user_agent").arg(user_agent);
+        }
+        if !headers.is_empty() {
+            cmd.arg("-headers").arg(headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect::<String>());
+        }
+    }
+    cmd.arg("-i").arg(input);
+}
+
+/// Temporary inputs (subtitles, chapters, cover) that are removed once the download is over,
+/// however it ends.
+#[derive(Default)]
+struct TempFiles(Vec<PathBuf>);
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn set_status(shared_state: &Mutex<Vec<(LinkInfo, DownloadStatus)>>, link_id: usize, status: DownloadStatus) {
+    let mut downloads = shared_state.lock();
+    if let Some(pos) = downloads.iter().position(|(li, _)| li.id == link_id) {
+        downloads[pos].1 = status;
+    }
+}
+
+async fn complete(link_info: &LinkInfo, output_file: &Path, settings: &Settings, shared_state: &Mutex<Vec<(LinkInfo, DownloadStatus)>>, link_id: usize) -> Result<()> {
+    let size_mb = fs::metadata(output_file)?.len() as f64 / 1_048_576.0;
+    if settings.write_nfo {
+        // Missing metadata shouldn't turn a finished download into a failure
+        let (_, layout) = naming::layout_template(&settings.name_template, settings.season_folders, link_info);
+        if let Err(e) = nfo::write_nfo(link_info, output_file, layout) {
+            link_info.warnings.lock().push(format!("NFO not written: {}", e));
+        }
+    }
+    // Hashing reads the whole file, so it's kept off the async workers
+    let (link, file) = (link_info.clone(), output_file.to_path_buf());
+    let recorded = tokio::task::spawn_blocking(move || archive::record(&link, &file)).await.map_err(anyhow::Error::from).and_then(|r| r);
+    if let Err(e) = recorded {
+        link_info.warnings.lock().push(format!("Not added to the archive: {}", e));
+    }
+    set_status(shared_state, link_id, DownloadStatus::Completed { size_mb });
+    Ok(())
+}
+
+/// Checks a finished file when verification is on. Files that can't be checked, e.g. without
+/// ffprobe, pass with a warning.
+async fn check_output(link_info: &LinkInfo, output_file: &Path, settings: &Settings, duration: Option<f64>, streams: Option<StreamCounts>) -> Option<String> {
+    if !settings.verify_downloads {
+        return None;
+    }
+    match verify::verify(settings, output_file, duration, streams).await {
+        Ok(problem) => problem,
+        Err(e) => {
+            link_info.warnings.lock().push(format!("Not verified: {}", e));
+            None
+        }
+    }
+}
+
+/// Fetches a progressive file into its part file, reporting progress as a share of its size.
+async fn fetch_direct(link_info: &LinkInfo, part: &Path, settings: &Settings, shared_state: &Mutex<Vec<(LinkInfo, DownloadStatus)>>, link_id: usize) -> Result<u64> {
+    direct::download(&http::streaming_client(settings)?, link_info, part, settings, |p| {
+        let progress = p.total.filter(|&t| t > 0).map(|t| (p.downloaded as f64 / t as f64 * 100.0).min(100.0)).unwrap_or(0.0);
+        let size_mb = p.downloaded as f64 / 1_048_576.0;
+        set_status(shared_state, link_id, DownloadStatus::Downloading { progress, speed: Speed::BytesPerSec(p.bytes_per_sec), size_mb });
+    })
+    .await
+}
 
 pub async fn download_stream(
-    link_info: LinkInfo,
-    folder: PathBuf,
+    mut link_info: LinkInfo,
+    output_file: PathBuf,
     settings: Settings,
-    all_links: Vec<LinkInfo>,
     shared_state: Arc<Mutex<Vec<(LinkInfo, DownloadStatus)>>>,
     link_id: usize,
 ) -> Result<()> {
-    let output_file = get_output_file(&link_info, &folder, &all_links);
+    let ext = container::output_extension(settings.container, &link_info.url);
+    let profile = transcode::active_profile(&settings);
+    if let Some(parent) = output_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
 
     // Set status to Starting
     {
@@ -28,24 +206,199 @@ pub async fn download_stream(
         }
     }
 
+    let client = http::client(&settings)?;
+    // DASH text tracks are fetched and checked like any other subtitle
+    let dash_selection = dash::resolve(&client, &link_info, settings.retries).await;
+    if let Some(selection) = &dash_selection {
+        link_info.subtitles.extend(selection.subtitles.iter().cloned());
+        for name in &selection.skipped_text {
+            link_info.warnings.lock().push(format!("Subtitle '{}' skipped: only segmented WebVTT text tracks can be muxed", name));
+        }
+    }
+    let prepared = if settings.sidecar_subtitles || container::supports_subtitles(ext) {
+        prepare_subtitles(&client, &link_info, &output_file, &settings).await
+    } else {
+        Vec::new()
+    };
+    let mut temp_files = TempFiles::default();
+    if !settings.sidecar_subtitles {
+        temp_files.0.extend(prepared.iter().map(|(_, path)| path.clone()));
+    }
+    let subtitle_inputs: &[(Subtitle, PathBuf)] = if container::supports_subtitles(ext) { &prepared } else { &[] };
+    let dash_text: &[dash::Track] = match &dash_selection {
+        Some(selection) if container::supports_subtitles(ext) => &selection.text,
+        Some(selection) => {
+            for track in &selection.text {
+                link_info.warnings.lock().push(format!("Subtitle '{}' skipped: the {} container can't hold it", track.label.as_deref().unwrap_or_default(), ext));
+            }
+            &[]
+        }
+        None => &[],
+    };
+    let font_attachments = if ext == "mkv" { collect_fonts(&link_info, subtitle_inputs, &settings) } else { Vec::new() };
+
+    let cover = match (&link_info.logo, ext) {
+        (Some(logo), "mkv") => fetch_cover(&client, &link_info, logo, &output_file, &settings).await,
+        _ => None,
+    };
+    if let Some((path, _)) = &cover {
+        temp_files.0.push(path.clone());
+    }
+
+    let chapters_file = output_file.with_file_name(format!("{}.anilink-chapters.txt", output_file.file_stem().unwrap_or_default().to_string_lossy()));
+    let has_chapters = !link_info.chapters.is_empty()
+        && ext != "ts"
+        && fs::write(&chapters_file, chapters::to_ffmetadata(&link_info.chapters, link_info.duration)).is_ok();
+    if has_chapters {
+        temp_files.0.push(chapters_file.clone());
+    }
+
+    // Extra inputs need explicit stream maps, which in turn need a single HLS variant to map from
+    let needs_maps = !subtitle_inputs.is_empty() || !link_info.audio.is_empty() || dash_selection.is_some();
+    let mut input = if !needs_maps || dash_selection.is_some() {
+        ResolvedInput { video: link_info.url.clone(), audio: None }
+    } else {
+        hls::resolve_input(&client, &link_info, settings.retries).await
+    };
+
+    // Progressive files are fetched over HTTP, ffmpeg only runs afterwards when there's something to mux
+    let direct_part = (settings.direct_downloads && direct::is_direct(&link_info.url)).then(|| direct::part_path(&output_file, &link_info.url));
+    if let Some(part) = &direct_part {
+        let needs_ffmpeg = needs_maps || has_chapters || cover.is_some() || !font_attachments.is_empty() || profile.is_some()
+            || url_extension(&link_info.url).as_deref() != Some(ext);
+        for attempt in 1..=settings.retries.max(1) {
+            if let Err(e) = fetch_direct(&link_info, part, &settings, &shared_state, link_id).await {
+                // The part file stays so the next run can resume it
+                set_status(&shared_state, link_id, DownloadStatus::Failed { error: e.to_string() });
+                return Err(e);
+            }
+            if needs_ffmpeg {
+                break;
+            }
+            // The size was checked while fetching. The playlist's duration is too rough to judge a
+            // file by, only one ffprobe measured beforehand is compared
+            let reason = match link_info.probed_duration {
+                Some(duration) => check_output(&link_info, part, &settings, Some(duration), None).await,
+                None => None,
+            };
+            if let Some(reason) = reason.as_ref().filter(|_| attempt < settings.retries) {
+                // The server considers the part complete, so it starts over rather than resuming
+                link_info.warnings.lock().push(format!("Attempt {} incomplete ({}), retrying", attempt, reason));
+                direct::remove_part(part);
+                set_status(&shared_state, link_id, DownloadStatus::Starting);
+                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                continue;
+            }
+            if let Err(e) = fs::rename(part, &output_file) {
+                set_status(&shared_state, link_id, DownloadStatus::Failed { error: e.to_string() });
+                return Err(e.into());
+            }
+            direct::remove_part(part);
+            if let Some(reason) = reason {
+                // The file stays for the user to judge
+                set_status(&shared_state, link_id, DownloadStatus::Incomplete { reason: reason.clone() });
+                return Err(anyhow::anyhow!("Incomplete after {} retries: {}", settings.retries, reason));
+            }
+            return complete(&link_info, &output_file, &settings, &shared_state, link_id).await;
+        }
+        input = ResolvedInput { video: part.to_string_lossy().to_string(), audio: None };
+    }
+
+    // Extra tracks are numbered after the stream's own audio, so whether it has any must be known
+    let own_audio = if dash_selection.is_none() && input.audio.is_none() && !link_info.audio.is_empty() {
+        match probe::ffprobe(&settings, &input.video, direct_part.is_none().then_some(&link_info)).await {
+            Ok(info) => info.streams.audio > 0,
+            Err(e) => {
+                link_info.warnings.lock().push(format!("Stream's own audio left out, couldn't tell whether it has any: {}", e));
+                false
+            }
+        }
+    } else {
+        false
+    };
+
     for attempt in 1..=settings.retries {
         let mut cmd = Command::new(&settings.ffmpeg_path);
         cmd.arg("-y").arg("-progress").arg("pipe:1");
 
-        if let Some(referer) = &link_info.referer {
-            cmd.arg("-headers").arg(format!("Referer: {}\r\n", referer));
+        add_input(&mut cmd, &input.video, &link_info);
+        if let Some(audio) = &input.audio {
+            add_input(&mut cmd, audio, &link_info);
+        }
+        for track in &link_info.audio {
+            add_input(&mut cmd, &track.url, &link_info);
+        }
+        for (_, path) in subtitle_inputs {
+            cmd.arg("-i").arg(path);
+        }
+        let first_extra_audio = if input.audio.is_some() { 2 } else { 1 };
+        let first_sub = first_extra_audio + link_info.audio.len();
+        if has_chapters {
+            cmd.arg("-i").arg(&chapters_file);
+            cmd.arg("-map_chapters").arg((first_sub + subtitle_inputs.len()).to_string());
         }
 
-        cmd.arg("-i").arg(&link_info.url);
+        // The stream's own audio, as (map, language, title). Its length is where extra tracks start.
+        let main_audio: Vec<(String, Option<String>, Option<String>)> = match (&dash_selection, &input.audio, link_info.audio.is_empty()) {
+            (Some(selection), _, _) => selection.audio.iter()
+                .map(|a| (format!("0:a:{}", a.index), a.language.clone(), a.label.clone()))
+                .collect(),
+            (None, Some(_), _) => vec![("1:a:0".to_string(), None, None)],
+            (None, None, true) => vec![("0:a?".to_string(), None, None)],
+            // With extra tracks the stream's own audio is kept to one track, so theirs follow at known indexes
+            (None, None, false) if own_audio => vec![("0:a:0".to_string(), None, None)],
+            (None, None, false) => Vec::new(),
+        };
 
-        for sub in &link_info.subtitles {
-            cmd.arg("-i").arg(&sub.url);
+        if needs_maps {
+            match dash_selection.as_ref().and_then(|s| s.video) {
+                Some(index) => cmd.arg("-map").arg(format!("0:v:{}", index)),
+                None => cmd.arg("-map").arg("0:v?"),
+            };
+            for (map, _, _) in &main_audio {
+                cmd.arg("-map").arg(map);
+            }
+            for i in 0..link_info.audio.len() {
+                cmd.arg("-map").arg(format!("{}:a:0", first_extra_audio + i));
+            }
+            for i in 0..subtitle_inputs.len() {
+                cmd.arg("-map").arg(format!("{}:s", first_sub + i));
+            }
+            // Older ffmpeg builds don't read DASH text tracks, which shouldn't fail the episode.
+            // ffmpeg tags them with the manifest's language itself, and since they may be
+            // missing they get no per-stream options that would then point nowhere.
+            for track in dash_text {
+                cmd.arg("-map").arg(format!("0:s:{}?", track.index));
+            }
         }
 
-        cmd.arg("-c").arg("copy");
+        cmd.args(transcode::ffmpeg_args(profile));
+        let copies_adts_audio = transcode::copies_audio(profile) && direct_part.is_none() && dash_selection.is_none() && container::has_adts_audio(&link_info.url);
+        cmd.args(container::ffmpeg_args(ext, !subtitle_inputs.is_empty() || !dash_text.is_empty(), copies_adts_audio));
+
+        for (i, (_, language, title)) in main_audio.iter().enumerate() {
+            if let Some(language) = language {
+                cmd.arg(format!("-metadata:s:a:{}", i)).arg(format!("language={}", lang::iso639_2(title.as_deref().unwrap_or_default(), Some(language))));
+            }
+            if let Some(title) = title {
+                cmd.arg(format!("-metadata:s:a:{}", i)).arg(format!("title={}", title));
+            }
+        }
+        for (i, track) in link_info.audio.iter().enumerate() {
+            let index = main_audio.len() + i;
+            let lang = lang::iso639_2(&track.name, track.language.as_deref());
+            cmd.arg(format!("-metadata:s:a:{}", index)).arg(format!("language={}", lang));
+            cmd.arg(format!("-metadata:s:a:{}", index)).arg(format!("title={}", track.name));
+            if track.default {
+                for i in 0..main_audio.len() {
+                    cmd.arg(format!("-disposition:a:{}", i)).arg("0");
+                }
+                cmd.arg(format!("-disposition:a:{}", index)).arg("default");
+            }
+        }
 
-        for (i, sub) in link_info.subtitles.iter().enumerate() {
-            let lang = sub.name.chars().take(3).collect::<String>().to_lowercase();
+        for (i, (sub, _)) in subtitle_inputs.iter().enumerate() {
+            let lang = lang::iso639_2(&sub.name, sub.language.as_deref());
             cmd.arg(format!("-metadata:s:s:{}", i)).arg(format!("language={}", lang));
             cmd.arg(format!("-metadata:s:s:{}", i)).arg(format!("title={}", sub.name));
             if sub.default {
@@ -53,7 +406,24 @@ pub async fn download_stream(
             }
         }
 
-        cmd.arg("-metadata").arg(format!("title={}", link_info.name));
+
+        for (i, font) in font_attachments.iter().enumerate() {
+            cmd.arg("-attach").arg(font);
+            cmd.arg(format!("-metadata:s:t:{}", i)).arg(format!("mimetype={}", fonts::mimetype(font)));
+            cmd.arg(format!("-metadata:s:t:{}", i)).arg(format!("filename={}", font.file_name().unwrap_or_default().to_string_lossy()));
+        }
+
+        if let Some((path, mimetype)) = &cover {
+            let i = font_attachments.len();
+            cmd.arg("-attach").arg(path);
+            cmd.arg(format!("-metadata:s:t:{}", i)).arg(format!("mimetype={}", mimetype));
+            // Matroska players look for an attachment with this name
+            cmd.arg(format!("-metadata:s:t:{}", i)).arg(format!("filename=cover.{}", path.extension().unwrap_or_default().to_string_lossy()));
+        }
+
+        for (key, value) in metadata::tags(&link_info) {
+            cmd.arg("-metadata").arg(format!("{}={}", key, value));
+        }
 
         if let Some(speed_limit) = &settings.speed_limit {
             cmd.arg("-maxrate").arg(speed_limit);
@@ -68,7 +438,12 @@ pub async fn download_stream(
         let stderr = child.stderr.take().unwrap();
         let mut reader = AsyncBufReader::new(stderr).lines();
 
-        let mut duration: Option<f64> = None;
+        let mut duration: Option<f64> = link_info.duration;
+        // What the finished file is checked against: ffmpeg's reading of the source, never #EXTINF
+        let mut source_duration: Option<f64> = link_info.probed_duration;
+        // Which input ffmpeg is describing, and the streams it lists for the output
+        let mut input_index: Option<usize> = None;
+        let mut output_streams: Option<StreamCounts> = None;
         let start_time = tokio::time::Instant::now();
 
         while let Some(line) = reader.next_line().await? {
@@ -83,14 +458,27 @@ pub async fn download_stream(
                 drop(downloads);
             }
 
-            if line.contains("Duration") {
-                duration = parse_ffmpeg_duration(&line).or(duration);
+            if let Some(index) = line.strip_prefix("Input #") {
+                input_index = index.split(',').next().and_then(|i| i.parse().ok());
+            } else if line.starts_with("Output #") {
+                input_index = None;
+                output_streams = Some(StreamCounts::default());
+            } else if let Some(kind) = parse_ffmpeg_stream(&line) {
+                if let Some(streams) = &mut output_streams {
+                    streams.add(kind);
+                }
+            } else if line.contains("Duration") {
+                // Subtitle and chapter inputs have durations of their own, the episode's is the first input's
+                if let Some(parsed) = parse_ffmpeg_duration(&line).filter(|_| input_index == Some(0)) {
+                    duration = Some(parsed);
+                    source_duration = Some(parsed);
+                }
             } else if line.contains("time=") && duration.is_some() {
                 if let Some(current_time) = parse_ffmpeg_time(&line) {
                     let total_duration = duration.unwrap();
                     let progress = (current_time / total_duration * 100.0).min(100.0);
                     let elapsed = start_time.elapsed().as_secs_f64();
-                    let speed = if elapsed > 0.0 { current_time / elapsed } else { 0.0 };
+                    let speed = Speed::Realtime(if elapsed > 0.0 { current_time / elapsed } else { 0.0 });
                     let size_mb = fs::metadata(&output_file).map(|m| m.len() as f64 / 1_048_576.0).unwrap_or(0.0);
 
                     // Update shared state only if not paused
@@ -107,21 +495,42 @@ pub async fn download_stream(
         let exit_status = child.wait().await?;
 
         if exit_status.success() {
-            let size_mb = fs::metadata(&output_file)?.len() as f64 / 1_048_576.0;
-            
-            // Update shared state to completed
-            let mut downloads = shared_state.lock();
-            if let Some(pos) = downloads.iter().position(|(li, _)| li.id == link_id) {
-                downloads[pos].1 = DownloadStatus::Completed { size_mb };
+            match check_output(&link_info, &output_file, &settings, source_duration, output_streams).await {
+                None => {
+                    if let Some(part) = &direct_part {
+                        direct::remove_part(part);
+                    }
+                    return complete(&link_info, &output_file, &settings, &shared_state, link_id).await;
+                }
+                // Truncated files usually come from a dropped connection, another attempt tends to get everything
+                Some(reason) if attempt < settings.retries => {
+                    link_info.warnings.lock().push(format!("Attempt {} incomplete ({}), retrying", attempt, reason));
+                    set_status(&shared_state, link_id, DownloadStatus::Starting);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                    // A short direct download would only be muxed into a short file again
+                    if let Some(part) = &direct_part {
+                        direct::remove_part(part);
+                        if let Err(e) = fetch_direct(&link_info, part, &settings, &shared_state, link_id).await {
+                            set_status(&shared_state, link_id, DownloadStatus::Failed { error: e.to_string() });
+                            return Err(e);
+                        }
+                    }
+                }
+                Some(reason) => {
+                    // The file stays for the user to judge, only the direct download it came from goes
+                    if let Some(part) = &direct_part {
+                        direct::remove_part(part);
+                    }
+                    set_status(&shared_state, link_id, DownloadStatus::Incomplete { reason: reason.clone() });
+                    return Err(anyhow::anyhow!("Incomplete after {} retries: {}", settings.retries, reason));
+                }
             }
-            
-            return Ok(());
         } else if attempt == settings.retries {
             // Update shared state to failed
             let mut downloads = shared_state.lock();
             if let Some(pos) = downloads.iter().position(|(li, _)| li.id == link_id) {
                 downloads[pos].1 = DownloadStatus::Failed {
-                    error: format!("ffmpeg exited with code {}", exit_status.code().unwrap_or(-1)),
+                    error: AppError::FfmpegError(exit_status.code().unwrap_or(-1)).to_string(),
                 };
             }
             
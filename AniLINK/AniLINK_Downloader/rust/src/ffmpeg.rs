@@ -15,6 +15,15 @@ pub struct ResolveResult {
     pub config_needs_update: bool,
 }
 
+/// ffprobe ships alongside ffmpeg, so it's looked up next to the configured binary.
+pub fn ffprobe_path(ffmpeg_path: &str) -> String {
+    let path = Path::new(ffmpeg_path);
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) if name.contains("ffmpeg") => path.with_file_name(name.replacen("ffmpeg", "ffprobe", 1)).to_string_lossy().to_string(),
+        _ => "ffprobe".to_string(),
+    }
+}
+
 pub async fn resolve_path(ffmpeg_path: &str, config_dir: &Path, term: &Term) -> Result<ResolveResult> {
     // Check if system ffmpeg is available
     if ffmpeg_path == "ffmpeg" || ffmpeg_path.is_empty() {
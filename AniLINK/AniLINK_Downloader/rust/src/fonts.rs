@@ -0,0 +1,71 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref INLINE_FONT_RE: Regex = Regex::new(r"\\fn([^\\}]+)").unwrap();
+}
+
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc"];
+
+/// Font families an ASS script uses, from its styles and any inline `\fn` overrides.
+pub fn referenced_fonts(script: &str) -> Vec<String> {
+    let mut fonts: Vec<String> = Vec::new();
+    let mut add = |name: &str| {
+        // A leading @ only asks for vertical layout of the same family
+        let name = name.trim().trim_start_matches('@').to_string();
+        if !name.is_empty() && !fonts.iter().any(|f| f.eq_ignore_ascii_case(&name)) {
+            fonts.push(name);
+        }
+    };
+
+    let mut in_styles = false;
+    let mut fontname_idx = 1;
+    for line in script.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_styles = line.eq_ignore_ascii_case("[V4+ Styles]") || line.eq_ignore_ascii_case("[V4 Styles]");
+        } else if in_styles {
+            if let Some(format) = line.strip_prefix("Format:") {
+                fontname_idx = format.split(',').position(|f| f.trim().eq_ignore_ascii_case("Fontname")).unwrap_or(1);
+            } else if let Some(style) = line.strip_prefix("Style:") {
+                if let Some(name) = style.split(',').nth(fontname_idx) {
+                    add(name);
+                }
+            }
+        } else if line.starts_with("Dialogue:") {
+            for c in INLINE_FONT_RE.captures_iter(line) {
+                add(&c[1]);
+            }
+        }
+    }
+    fonts
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+/// Finds a font file for a family by file name, preferring an exact match over style variants
+/// such as `Family-Regular.ttf`.
+pub fn find_font(fonts_dir: &Path, family: &str) -> Option<PathBuf> {
+    let wanted = normalize(family);
+    let candidates: Vec<(String, PathBuf)> = fs::read_dir(fonts_dir).ok()?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()).is_some_and(|e| FONT_EXTENSIONS.contains(&e.to_lowercase().as_str())))
+        .map(|p| (normalize(&p.file_stem().unwrap_or_default().to_string_lossy()), p))
+        .collect();
+
+    candidates.iter().find(|(stem, _)| *stem == wanted)
+        .or_else(|| candidates.iter().find(|(stem, _)| *stem == format!("{}regular", wanted)))
+        .or_else(|| candidates.iter().filter(|(stem, _)| stem.starts_with(&wanted)).min_by_key(|(stem, _)| stem.len()))
+        .map(|(_, path)| path.clone())
+}
+
+pub fn mimetype(font: &Path) -> &'static str {
+    match font.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+        Some("otf") | Some("otc") => "application/vnd.ms-opentype",
+        _ => "application/x-truetype-font",
+    }
+}
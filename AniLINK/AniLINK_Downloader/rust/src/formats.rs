@@ -0,0 +1,371 @@
+use serde_json::{Map, Value};
+use crate::parser::{name_from_uri, new_link, parse_playlist, resolve_uri, ParseWarning, Playlist};
+use crate::types::{AudioTrack, LinkInfo, Subtitle};
+
+/// The job list formats `load` understands besides M3U.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    M3u,
+    UrlList,
+    Json,
+    Csv,
+}
+
+pub const INPUT_EXTENSIONS: &[&str] = &["m3u", "m3u8", "json", "csv", "txt"];
+
+/// Picks the format from the file extension when it has a telling one, else from the content.
+pub fn detect(name: Option<&str>, content: &str) -> InputFormat {
+    let ext = name
+        .map(|n| n.split(['?', '#']).next().unwrap_or(n))
+        .and_then(|n| n.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase());
+    match ext.as_deref() {
+        Some("m3u") | Some("m3u8") => return InputFormat::M3u,
+        Some("json") => return InputFormat::Json,
+        Some("csv") => return InputFormat::Csv,
+        _ => {}
+    }
+    let head = content.trim_start_matches('\u{feff}').trim_start();
+    let first_line = head.lines().next().unwrap_or_default().to_lowercase();
+    if head.starts_with("#EXTM3U") || head.contains("#EXTINF") {
+        InputFormat::M3u
+    } else if head.starts_with('[') || head.starts_with('{') {
+        InputFormat::Json
+    } else if first_line.contains(',') && split_csv_line(&first_line).iter().any(|c| c.trim() == "url") {
+        InputFormat::Csv
+    } else {
+        InputFormat::UrlList
+    }
+}
+
+/// Parses a job list in any supported format into the same shape as an M3U playlist.
+pub fn parse(content: &str, base: Option<&str>) -> Playlist {
+    match detect(base, content) {
+        InputFormat::M3u => parse_playlist(content, base),
+        InputFormat::UrlList => parse_url_list(content, base),
+        InputFormat::Json => parse_json(content, base),
+        InputFormat::Csv => parse_csv(content, base),
+    }
+}
+
+fn fallback_name(url: &str, id: usize) -> String {
+    name_from_uri(url).unwrap_or_else(|| format!("Episode {}", id + 1))
+}
+
+/// One entry per line: `url`, `name|url` or `name|url|referer`. Lines starting with `#` are comments.
+pub fn parse_url_list(content: &str, base: Option<&str>) -> Playlist {
+    let mut playlist = Playlist::default();
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+        let (name, url, referer) = match parts.as_slice() {
+            [url] => (None, *url, None),
+            [name, url] => (Some(*name), *url, None),
+            [name, url, referer] => (Some(*name), *url, Some(*referer)),
+            _ => {
+                playlist.warnings.push(ParseWarning { line: idx + 1, message: "expected `url`, `name|url` or `name|url|referer`, skipped".to_string() });
+                continue;
+            }
+        };
+        if url.is_empty() {
+            playlist.warnings.push(ParseWarning { line: idx + 1, message: "entry has no URL, skipped".to_string() });
+            continue;
+        }
+        let id = playlist.links.len();
+        let name = name.filter(|n| !n.is_empty()).map(String::from).unwrap_or_else(|| fallback_name(url, id));
+        let mut link = new_link(id, &name, resolve_uri(base, url));
+        link.referer = referer.filter(|r| !r.is_empty()).map(String::from);
+        playlist.links.push(link);
+    }
+    playlist
+}
+
+fn string(object: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|k| object.get(*k)?.as_str()).map(str::trim).filter(|s| !s.is_empty()).map(String::from)
+}
+
+fn number(object: &Map<String, Value>, key: &str) -> Option<u32> {
+    let value = object.get(key)?;
+    value.as_u64().map(|n| n as u32).or_else(|| value.as_str()?.trim().parse().ok())
+}
+
+/// Track lists may be plain URLs or objects with name, url, language and default.
+fn tracks(value: Option<&Value>, base: Option<&str>, default_name: &str) -> Vec<(String, String, bool, Option<String>)> {
+    let Some(Value::Array(items)) = value else { return Vec::new() };
+    items.iter()
+        .filter_map(|item| match item {
+            Value::String(url) => Some((default_name.to_string(), resolve_uri(base, url), false, None)),
+            Value::Object(track) => Some((
+                string(track, &["name", "label"]).unwrap_or_else(|| default_name.to_string()),
+                resolve_uri(base, &string(track, &["url", "file", "uri"])?),
+                track.get("default").and_then(Value::as_bool).unwrap_or(false),
+                string(track, &["language", "lang"]),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+fn link_from_json(object: &Map<String, Value>, id: usize, base: Option<&str>) -> Option<LinkInfo> {
+    let url = string(object, &["url", "stream"])?;
+    let name = string(object, &["name", "title"]).unwrap_or_else(|| fallback_name(&url, id));
+    let mut link = new_link(id, &name, resolve_uri(base, &url));
+    link.referer = string(object, &["referer", "referrer"]);
+    if let Some(Value::Object(headers)) = object.get("headers") {
+        link.headers = headers.iter().filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string()))).collect();
+    }
+    link.subtitles = tracks(object.get("subtitles"), base, "Subtitle").into_iter()
+        .map(|(name, url, default, language)| Subtitle { name, url, default, language })
+        .collect();
+    link.audio = tracks(object.get("audio"), base, "Audio").into_iter()
+        .map(|(name, url, default, language)| AudioTrack { name, url, default, language })
+        .collect();
+    if let Some(quality) = string(object, &["quality"]) {
+        // Same shape as the suffix M3U names carry, e.g. "[1080p]"
+        link.quality = Some(format!("[{}]", quality.trim_start_matches('[').trim_end_matches(']')));
+    }
+    link.show = string(object, &["show"]).or(link.show);
+    link.season = number(object, "season").or(link.season);
+    link.episode = number(object, "episode").or(link.episode);
+    link.logo = string(object, &["logo", "cover"]).map(|logo| resolve_uri(base, &logo));
+    Some(link)
+}
+
+/// An array of link objects, or an object with a `title` and a `links` (or `episodes`) array.
+/// Fields follow `LinkInfo`: name, url, referer, headers, subtitles, audio, quality, show,
+/// season, episode and logo.
+pub fn parse_json(content: &str, base: Option<&str>) -> Playlist {
+    let mut playlist = Playlist::default();
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let root: Value = match serde_json::from_str(content) {
+        Ok(root) => root,
+        Err(e) => {
+            playlist.warnings.push(ParseWarning { line: e.line(), message: format!("invalid JSON: {}", e) });
+            return playlist;
+        }
+    };
+    let (items, key) = match &root {
+        Value::Array(items) => (items, None),
+        Value::Object(object) => {
+            playlist.title = string(object, &["title", "name"]);
+            match ["links", "episodes"].into_iter().find_map(|key| Some((object.get(key)?.as_array()?, Some(key)))) {
+                Some(found) => found,
+                None => {
+                    playlist.warnings.push(ParseWarning { line: 1, message: "expected a `links` array".to_string() });
+                    return playlist;
+                }
+            }
+        }
+        _ => {
+            playlist.warnings.push(ParseWarning { line: 1, message: "expected an array of links".to_string() });
+            return playlist;
+        }
+    };
+    let lines = array_start(content, key).map(|start| element_lines(content, start)).unwrap_or_default();
+    for (idx, item) in items.iter().enumerate() {
+        match item.as_object().and_then(|object| link_from_json(object, playlist.links.len(), base)) {
+            Some(link) => playlist.links.push(link),
+            None => playlist.warnings.push(ParseWarning { line: lines.get(idx).copied().unwrap_or(1), message: format!("entry {} has no url, skipped", idx + 1) }),
+        }
+    }
+    playlist
+}
+
+/// Reads the JSON value starting at `pos`, returning it with the offset just past it.
+fn value_at(content: &str, pos: usize) -> Option<(Value, usize)> {
+    let mut values = serde_json::Deserializer::from_str(&content[pos..]).into_iter::<Value>();
+    let value = values.next()?.ok()?;
+    Some((value, pos + values.byte_offset()))
+}
+
+/// Offset of the next character that isn't whitespace or one of `separators`.
+fn skip(content: &str, pos: usize, separators: &str) -> usize {
+    let rest = &content[pos..];
+    pos + rest.len() - rest.trim_start_matches(|c: char| c.is_whitespace() || separators.contains(c)).len()
+}
+
+/// Byte offset of the `[` opening the link array: the root itself, or the value of the root
+/// object's `key`. The object is walked member by member, so the key's name showing up inside
+/// another value doesn't matter.
+fn array_start(content: &str, key: Option<&str>) -> Option<usize> {
+    let start = skip(content, 0, "");
+    let Some(key) = key else { return content[start..].starts_with('[').then_some(start) };
+    let mut pos = start + content[start..].strip_prefix('{').map(|_| 1)?;
+    loop {
+        pos = skip(content, pos, ",");
+        let (name, after_name) = value_at(content, pos)?;
+        pos = skip(content, after_name, ":");
+        if name.as_str()? == key {
+            return content[pos..].starts_with('[').then_some(pos);
+        }
+        pos = value_at(content, pos)?.1;
+    }
+}
+
+/// Line each element of the array opened at `start` begins on. Parsed values don't keep their
+/// position, so the array is read again one element at a time.
+fn element_lines(content: &str, start: usize) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut pos = start + 1;
+    loop {
+        pos = skip(content, pos, ",");
+        if pos >= content.len() || content[pos..].starts_with(']') {
+            return lines;
+        }
+        lines.push(content[..pos].matches('\n').count() + 1);
+        match value_at(content, pos) {
+            Some((_, next)) => pos = next,
+            None => return lines,
+        }
+    }
+}
+
+/// CSV records with the line each starts on. Quoted fields may span lines.
+fn csv_records(content: &str) -> Vec<(usize, String)> {
+    let mut records: Vec<(usize, String)> = Vec::new();
+    let mut open = false;
+    for (idx, line) in content.lines().enumerate() {
+        match records.last_mut() {
+            Some((_, record)) if open => {
+                record.push('\n');
+                record.push_str(line);
+            }
+            _ if line.trim().is_empty() => continue,
+            _ => records.push((idx + 1, line.to_string())),
+        }
+        // `""` escapes come in pairs, so an odd count leaves a quote open
+        open ^= line.matches('"').count() % 2 == 1;
+    }
+    records
+}
+
+/// Splits one CSV record, honouring double quotes and `""` escapes.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// CSV with a header row naming the columns: url is required, name, referer, quality,
+/// subtitles and audio are optional. Track cells hold `;`-separated `url` or `name|url` items.
+pub fn parse_csv(content: &str, base: Option<&str>) -> Playlist {
+    let mut playlist = Playlist::default();
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut records = csv_records(content).into_iter();
+    let Some((_, header)) = records.next() else { return playlist };
+    let columns: Vec<String> = split_csv_line(&header).iter().map(|c| c.trim().to_lowercase()).collect();
+    if !columns.iter().any(|c| c == "url") {
+        playlist.warnings.push(ParseWarning { line: 1, message: "the header row has no `url` column".to_string() });
+        return playlist;
+    }
+
+    for (line_no, record) in records {
+        let mut object = Map::new();
+        for (column, value) in columns.iter().zip(split_csv_line(&record)) {
+            let value = match column.as_str() {
+                "subtitles" | "audio" => Value::Array(
+                    value.split(';').map(str::trim).filter(|t| !t.is_empty())
+                        .map(|t| match t.split_once('|') {
+                            Some((name, url)) => serde_json::json!({ "name": name.trim(), "url": url.trim() }),
+                            None => Value::String(t.to_string()),
+                        })
+                        .collect(),
+                ),
+                _ => Value::String(value),
+            };
+            object.insert(column.clone(), value);
+        }
+        match link_from_json(&object, playlist.links.len(), base) {
+            Some(link) => playlist.links.push(link),
+            None => playlist.warnings.push(ParseWarning { line: line_no, message: "row has no url, skipped".to_string() }),
+        }
+    }
+    playlist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_format_from_the_extension_then_the_content() {
+        assert_eq!(detect(Some("https://host/list.json?token=1"), "#EXTM3U"), InputFormat::Json);
+        assert_eq!(detect(Some("jobs.CSV"), ""), InputFormat::Csv);
+        assert_eq!(detect(Some("list.txt"), "\u{feff}#EXTM3U\n"), InputFormat::M3u);
+        assert_eq!(detect(None, "  [{\"url\": \"https://a/1\"}]"), InputFormat::Json);
+        assert_eq!(detect(None, "name,URL,referer\nOne,https://a/1,\n"), InputFormat::Csv);
+        assert_eq!(detect(None, "https://a/1.mp4\nOne|https://a/2.mp4\n"), InputFormat::UrlList);
+    }
+
+    #[test]
+    fn url_lists_take_optional_names_and_referers() {
+        let playlist = parse_url_list("# comment\nhttps://cdn/Show%20-%2001.mp4\n\nShow - 02 [720p]|https://cdn/2.mp4|https://site/\na|b|c|d\n", None);
+        let links = &playlist.links;
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].url, "https://cdn/Show%20-%2001.mp4");
+        assert_eq!(links[0].referer, None);
+        assert_eq!((links[1].name.as_str(), links[1].quality.as_deref()), ("Show - 02", Some("[720p]")));
+        assert_eq!(links[1].episode, Some(2));
+        assert_eq!(links[1].referer.as_deref(), Some("https://site/"));
+        assert_eq!(playlist.warnings.iter().map(|w| w.line).collect::<Vec<_>>(), [5]);
+    }
+
+    #[test]
+    fn json_reads_link_fields_and_reports_entries_by_line() {
+        let content = r#"{
+  "title": "Show \"links\": [",
+  "note": {"links": "https://site/links"},
+  "links": [
+    {
+      "name": "Show - 01", "url": "https://cdn/1.m3u8", "referer": "https://site/",
+      "quality": "1080p", "season": "2",
+      "subtitles": ["https://cdn/1.en.vtt", {"label": "Español (Latinoamérica)", "file": "1.es.vtt", "default": true}],
+      "audio": [{"name": "Japanese", "url": "https://cdn/1.ja.m3u8", "language": "ja"}]
+    },
+    {"name": "no url"}
+  ]
+}"#;
+        let playlist = parse_json(content, Some("https://host/list.json"));
+        assert_eq!(playlist.title.as_deref(), Some("Show \"links\": ["));
+        let link = &playlist.links[0];
+        assert_eq!(link.quality.as_deref(), Some("[1080p]"));
+        assert_eq!((link.season, link.episode), (Some(2), Some(1)));
+        assert_eq!(link.subtitles[1].url, "https://host/1.es.vtt");
+        assert!(link.subtitles[1].default);
+        assert_eq!(link.audio[0].language.as_deref(), Some("ja"));
+        assert_eq!(playlist.warnings, [ParseWarning { line: 11, message: "entry 2 has no url, skipped".to_string() }]);
+
+        let invalid = parse_json("[\n{\"url\": }\n]", None);
+        assert_eq!(invalid.warnings[0].line, 2);
+    }
+
+    #[test]
+    fn csv_honours_quotes_and_track_cells() {
+        let playlist = parse_csv(
+            "name,url,subtitles\n\"Show, The - 01\",https://cdn/1.mp4,\"English|https://cdn/1.vtt;\nhttps://cdn/1.es.vtt\"\n\n\"Say \"\"Hi\"\"\",,\n",
+            None,
+        );
+        assert_eq!(playlist.links.len(), 1);
+        assert_eq!(playlist.links[0].name, "Show, The - 01");
+        let subtitles: Vec<_> = playlist.links[0].subtitles.iter().map(|s| (s.name.as_str(), s.url.as_str())).collect();
+        assert_eq!(subtitles, [("English", "https://cdn/1.vtt"), ("Subtitle", "https://cdn/1.es.vtt")]);
+        assert_eq!(split_csv_line("\"Say \"\"Hi\"\"\",,"), ["Say \"Hi\"", "", ""]);
+        assert_eq!(playlist.warnings.iter().map(|w| w.line).collect::<Vec<_>>(), [5]);
+    }
+}
@@ -0,0 +1,134 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{Client, Url};
+use std::collections::HashMap;
+use crate::http;
+use crate::types::LinkInfo;
+use crate::utils::url_extension;
+
+lazy_static! {
+    static ref ATTR_RE: Regex = Regex::new(r#"([A-Z0-9-]+)=("[^"]*"|[^,]*)"#).unwrap();
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub uri: String,
+    pub audio_group: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rendition {
+    pub media_type: String,
+    pub group_id: Option<String>,
+    pub uri: Option<String>,
+    pub default: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+    pub renditions: Vec<Rendition>,
+}
+
+/// Streams picked from a link: the media playlist to read video from and, when the variant
+/// keeps its audio in a separate rendition, that rendition's playlist.
+pub struct ResolvedInput {
+    pub video: String,
+    pub audio: Option<String>,
+}
+
+pub fn parse_attributes(s: &str) -> HashMap<String, String> {
+    ATTR_RE
+        .captures_iter(s)
+        .map(|c| (c[1].to_string(), c[2].trim_matches('"').to_string()))
+        .collect()
+}
+
+pub fn resolve_url(base: &str, uri: &str) -> String {
+    Url::parse(base)
+        .and_then(|b| b.join(uri))
+        .map(|u| u.to_string())
+        .unwrap_or_else(|_| uri.to_string())
+}
+
+/// Parses a master playlist, returning `None` for media playlists.
+pub fn parse_master(content: &str, base_url: &str) -> Option<MasterPlaylist> {
+    let mut master = MasterPlaylist::default();
+    let mut lines = content.lines().map(str::trim).filter(|l| !l.is_empty());
+
+    while let Some(line) = lines.next() {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let attrs = parse_attributes(attrs);
+            let Some(uri) = lines.by_ref().find(|l| !l.starts_with('#')) else { break };
+            master.variants.push(Variant {
+                bandwidth: attrs.get("BANDWIDTH").and_then(|v| v.parse().ok()).unwrap_or(0),
+                resolution: attrs.get("RESOLUTION").and_then(|v| {
+                    let (w, h) = v.split_once('x')?;
+                    Some((w.parse().ok()?, h.parse().ok()?))
+                }),
+                uri: resolve_url(base_url, uri),
+                audio_group: attrs.get("AUDIO").cloned(),
+            });
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attrs = parse_attributes(attrs);
+            master.renditions.push(Rendition {
+                media_type: attrs.get("TYPE").cloned().unwrap_or_default(),
+                group_id: attrs.get("GROUP-ID").cloned(),
+                uri: attrs.get("URI").map(|u| resolve_url(base_url, u)),
+                default: attrs.get("DEFAULT").is_some_and(|v| v == "YES"),
+            });
+        }
+    }
+
+    if master.variants.is_empty() { None } else { Some(master) }
+}
+
+/// Length of a media playlist, the sum of its segment durations.
+pub fn media_duration(content: &str) -> Option<f64> {
+    let durations: Vec<f64> = content.lines()
+        .filter_map(|l| l.trim().strip_prefix("#EXTINF:"))
+        .filter_map(|v| v.split(',').next()?.trim().parse().ok())
+        .collect();
+    (!durations.is_empty()).then(|| durations.iter().sum())
+}
+
+/// Highest resolution wins, bandwidth breaks ties. Shared with DASH representations.
+pub fn variant_rank(resolution: Option<(u32, u32)>, bandwidth: u64) -> (u32, u64) {
+    (resolution.map(|(_, h)| h).unwrap_or(0), bandwidth)
+}
+
+pub fn best_variant(master: &MasterPlaylist) -> Option<&Variant> {
+    master.variants.iter().max_by_key(|v| variant_rank(v.resolution, v.bandwidth))
+}
+
+fn audio_rendition<'a>(master: &'a MasterPlaylist, variant: &Variant) -> Option<&'a str> {
+    let group = variant.audio_group.as_deref()?;
+    let renditions: Vec<_> = master.renditions.iter()
+        .filter(|r| r.media_type == "AUDIO" && r.group_id.as_deref() == Some(group) && r.uri.is_some())
+        .collect();
+    renditions.iter().find(|r| r.default).or(renditions.first()).and_then(|r| r.uri.as_deref())
+}
+
+/// Picks a single variant out of an HLS master playlist so its streams can be mapped
+/// explicitly. Anything that isn't a reachable master playlist is passed through untouched.
+pub async fn resolve_input(client: &Client, link_info: &LinkInfo, retries: u32) -> ResolvedInput {
+    let passthrough = ResolvedInput { video: link_info.url.clone(), audio: None };
+    if url_extension(&link_info.url).as_deref() != Some("m3u8") {
+        return passthrough;
+    }
+    let Ok(content) = http::get_text(client, &link_info.url, link_info, retries).await else {
+        return passthrough;
+    };
+    let Some(master) = parse_master(&content, &link_info.url) else {
+        return passthrough;
+    };
+    match best_variant(&master) {
+        Some(variant) => ResolvedInput {
+            video: variant.uri.clone(),
+            audio: audio_rendition(&master, variant).map(String::from),
+        },
+        None => passthrough,
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder, Url};
+use std::time::Duration;
+use crate::types::{LinkInfo, Settings};
+
+pub fn client(settings: &Settings) -> Result<Client> {
+    Ok(Client::builder()
+        .timeout(Duration::from_secs(settings.timeout))
+        .build()?)
+}
+
+/// For long transfers: the timeout only covers connecting, stalls are detected per read instead.
+pub fn streaming_client(settings: &Settings) -> Result<Client> {
+    Ok(Client::builder()
+        .connect_timeout(Duration::from_secs(settings.timeout))
+        .build()?)
+}
+
+/// Every header a link's host expects. Like the MPV script, an Origin is derived from the
+/// referer unless the playlist sets one.
+pub fn request_headers(link_info: &LinkInfo) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(referer) = &link_info.referer {
+        headers.push(("Referer".to_string(), referer.clone()));
+        if !link_info.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("Origin")) {
+            if let Ok(url) = Url::parse(referer) {
+                headers.push(("Origin".to_string(), url.origin().ascii_serialization()));
+            }
+        }
+    }
+    headers.extend(link_info.headers.iter().cloned());
+    headers
+}
+
+/// Adds the headers a link's host expects to a request.
+pub fn with_headers(request: RequestBuilder, link_info: &LinkInfo) -> RequestBuilder {
+    request_headers(link_info).into_iter().fold(request, |request, (name, value)| request.header(name, value))
+}
+
+/// Fetches a resource belonging to a link along with its content type, retrying transient failures.
+pub async fn get_with_type(client: &Client, url: &str, link_info: &LinkInfo, retries: u32) -> Result<(Vec<u8>, Option<String>)> {
+    let mut attempt = 1;
+    loop {
+        let result = async {
+            let resp = with_headers(client.get(url), link_info).send().await?.error_for_status()?;
+            let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(String::from);
+            Ok::<_, anyhow::Error>((resp.bytes().await?.to_vec(), content_type))
+        }
+        .await;
+        match result {
+            Ok(body) => return Ok(body),
+            Err(e) if attempt >= retries.max(1) => return Err(e),
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+pub async fn get_text_with_type(client: &Client, url: &str, link_info: &LinkInfo, retries: u32) -> Result<(String, Option<String>)> {
+    let (body, content_type) = get_with_type(client, url, link_info, retries).await?;
+    Ok((String::from_utf8_lossy(&body).into_owned(), content_type))
+}
+
+pub async fn get_text(client: &Client, url: &str, link_info: &LinkInfo, retries: u32) -> Result<String> {
+    Ok(get_text_with_type(client, url, link_info, retries).await?.0)
+}
@@ -0,0 +1,195 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref NOISE_RE: Regex = Regex::new(r"(?i)\b(cc|sdh|forced|full|signs?|songs?|subs?|subtitles?|captions?|dub|hardsub|default)\b").unwrap();
+    static ref QUALIFIER_RE: Regex = Regex::new(r"[(\[]([^)\]]*)[)\]]").unwrap();
+    static ref CODE_RE: Regex = Regex::new(r"^([a-zA-Z]{2,3})(?:[-_]([a-zA-Z]{2}|\d{3}|[a-zA-Z]{4}))?$").unwrap();
+}
+
+/// (ISO 639-1, ISO 639-2/B, names in English, natively and common spellings).
+/// Matroska expects the bibliographic 639-2 codes, hence "ger" and "fre".
+const LANGUAGES: &[(&str, &str, &[&str])] = &[
+    ("en", "eng", &["english", "inglés", "ingles", "anglais", "englisch"]),
+    ("ja", "jpn", &["japanese", "日本語", "japonés", "japones", "japonais", "japanisch"]),
+    ("es", "spa", &["spanish", "español", "espanol", "castellano", "castilian", "latino", "espagnol", "spanisch"]),
+    ("pt", "por", &["portuguese", "português", "portugues", "portugués", "portugais"]),
+    ("fr", "fre", &["french", "français", "francais", "francés", "frances", "französisch"]),
+    ("de", "ger", &["german", "deutsch", "alemán", "aleman", "allemand"]),
+    ("it", "ita", &["italian", "italiano", "italien", "italienisch"]),
+    ("ru", "rus", &["russian", "русский", "ruso", "russe", "russisch"]),
+    ("ar", "ara", &["arabic", "العربية", "árabe", "arabe", "arabisch"]),
+    ("zh", "chi", &["chinese", "中文", "简体中文", "繁體中文", "简体", "繁體", "mandarin", "cantonese", "chino", "chinois"]),
+    ("ko", "kor", &["korean", "한국어", "coreano", "coréen"]),
+    ("hi", "hin", &["hindi", "हिन्दी", "हिंदी"]),
+    ("id", "ind", &["indonesian", "bahasa indonesia", "indonesia"]),
+    ("ms", "may", &["malay", "bahasa melayu", "melayu"]),
+    ("th", "tha", &["thai", "ไทย"]),
+    ("vi", "vie", &["vietnamese", "tiếng việt", "tieng viet"]),
+    ("tr", "tur", &["turkish", "türkçe", "turkce"]),
+    ("pl", "pol", &["polish", "polski"]),
+    ("nl", "dut", &["dutch", "nederlands", "flemish"]),
+    ("sv", "swe", &["swedish", "svenska"]),
+    ("no", "nor", &["norwegian", "norsk", "bokmål"]),
+    ("da", "dan", &["danish", "dansk"]),
+    ("fi", "fin", &["finnish", "suomi"]),
+    ("cs", "cze", &["czech", "čeština", "cestina"]),
+    ("sk", "slo", &["slovak", "slovenčina"]),
+    ("hu", "hun", &["hungarian", "magyar"]),
+    ("ro", "rum", &["romanian", "română", "romana"]),
+    ("bg", "bul", &["bulgarian", "български"]),
+    ("hr", "hrv", &["croatian", "hrvatski"]),
+    ("sr", "srp", &["serbian", "српски", "srpski"]),
+    ("el", "gre", &["greek", "ελληνικά"]),
+    ("he", "heb", &["hebrew", "עברית"]),
+    ("uk", "ukr", &["ukrainian", "українська"]),
+    ("fa", "per", &["persian", "farsi", "فارسی"]),
+    ("bn", "ben", &["bengali", "bangla", "বাংলা"]),
+    ("ta", "tam", &["tamil", "தமிழ்"]),
+    ("te", "tel", &["telugu", "తెలుగు"]),
+    ("ca", "cat", &["catalan", "català"]),
+    ("tl", "fil", &["filipino", "tagalog"]),
+];
+
+/// Qualifiers that name a regional variant, mapped to their BCP 47 subtag.
+const REGIONS: &[(&str, &str)] = &[
+    ("brasil", "BR"), ("brazil", "BR"), ("brazilian", "BR"),
+    ("portugal", "PT"),
+    ("latinoamérica", "419"), ("latinoamerica", "419"), ("latin america", "419"), ("latam", "419"), ("latino", "419"),
+    ("españa", "ES"), ("espana", "ES"), ("spain", "ES"), ("castilian", "ES"), ("castellano", "ES"), ("european spanish", "ES"),
+    ("simplified", "Hans"), ("简体", "Hans"), ("简体中文", "Hans"), ("traditional", "Hant"), ("繁體", "Hant"), ("繁體中文", "Hant"),
+    ("canada", "CA"), ("canadian", "CA"), ("canadien", "CA"),
+    ("us", "US"), ("uk", "GB"), ("british", "GB"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Language {
+    pub iso639_1: &'static str,
+    pub iso639_2: &'static str,
+    pub region: Option<String>,
+}
+
+impl Language {
+    /// BCP 47 style tag such as "pt-BR", used where the regional variant should survive.
+    pub fn ietf(&self) -> String {
+        match &self.region {
+            Some(region) => format!("{}-{}", self.iso639_1, region),
+            None => self.iso639_1.to_string(),
+        }
+    }
+}
+
+fn from_code(code: &str) -> Option<Language> {
+    let caps = CODE_RE.captures(code.trim())?;
+    let lang = caps[1].to_lowercase();
+    let (iso639_1, iso639_2, _) = LANGUAGES.iter().find(|(one, two, _)| *one == lang || *two == lang || bibliographic_alias(two) == lang)?;
+    Some(Language {
+        iso639_1,
+        iso639_2,
+        region: caps.get(2).map(|r| match r.as_str().len() {
+            4 => capitalize(r.as_str()),
+            _ => r.as_str().to_uppercase(),
+        }),
+    })
+}
+
+/// Terminology codes that differ from the bibliographic ones we store.
+fn bibliographic_alias(code: &str) -> &str {
+    match code {
+        "fre" => "fra",
+        "ger" => "deu",
+        "chi" => "zho",
+        "dut" => "nld",
+        "cze" => "ces",
+        "slo" => "slk",
+        "rum" => "ron",
+        "gre" => "ell",
+        "per" => "fas",
+        "may" => "msa",
+        _ => code,
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let lower = s.to_lowercase();
+    let mut chars = lower.chars();
+    chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+fn region_of(text: &str) -> Option<String> {
+    let words: Vec<&str> = text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    REGIONS.iter()
+        .find(|(name, _)| if name.contains(' ') { text.contains(name) } else { words.contains(name) })
+        .map(|(_, region)| region.to_string())
+}
+
+fn from_name(label: &str) -> Option<Language> {
+    let lower = label.to_lowercase();
+    let base = QUALIFIER_RE.replace_all(&lower, " ");
+    let base = NOISE_RE.replace_all(&base, " ");
+    let base = base.trim_matches(|c: char| !c.is_alphanumeric()).trim();
+
+    let found = LANGUAGES.iter().find(|(_, _, names)| names.contains(&base)).or_else(|| {
+        // Labels like "English - Netflix" or "Portuguese Brazilian": the language leads
+        base.split(|c: char| c.is_whitespace() || "-_,/|".contains(c))
+            .find_map(|word| LANGUAGES.iter().find(|(_, _, names)| names.contains(&word)))
+    })?;
+
+    Some(Language {
+        iso639_1: found.0,
+        iso639_2: found.1,
+        region: region_of(&lower),
+    })
+}
+
+/// Works out a track's language from the playlist's LANGUAGE attribute when present,
+/// falling back to its display name. Returns `None` when it can't tell ("und").
+pub fn detect(name: &str, language_attr: Option<&str>) -> Option<Language> {
+    let from_attr = language_attr.and_then(|attr| from_code(attr).or_else(|| from_name(attr)));
+    let from_label = from_code(name).or_else(|| from_name(name));
+    match (from_attr, from_label) {
+        // The attribute is authoritative, but labels often carry the region it leaves out
+        (Some(mut lang), Some(label)) if lang.region.is_none() && label.iso639_2 == lang.iso639_2 => {
+            lang.region = label.region;
+            Some(lang)
+        }
+        (Some(lang), _) => Some(lang),
+        (None, label) => label,
+    }
+}
+
+pub fn iso639_2(name: &str, language_attr: Option<&str>) -> &'static str {
+    detect(name, language_attr).map(|l| l.iso639_2).unwrap_or("und")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_map_to_iso639_2_and_keep_their_region() {
+        let cases = [
+            ("English", None, "eng", "en"),
+            ("English [CC]", None, "eng", "en"),
+            ("English (SDH) - Netflix", None, "eng", "en"),
+            ("Japanese", None, "jpn", "ja"),
+            ("日本語", None, "jpn", "ja"),
+            ("German", None, "ger", "de"),
+            ("Deutsch", None, "ger", "de"),
+            ("Español (Latinoamérica)", None, "spa", "es-419"),
+            ("Español (España)", None, "spa", "es-ES"),
+            ("Português (Brasil)", None, "por", "pt-BR"),
+            ("Portuguese Brazilian", None, "por", "pt-BR"),
+            ("简体中文", None, "chi", "zh-Hans"),
+            ("Signs & Songs", None, "und", "und"),
+            ("Track 2", Some("fr-CA"), "fre", "fr-CA"),
+            ("Português (Brasil)", Some("por"), "por", "pt-BR"),
+            ("English", Some("deu"), "ger", "de"),
+        ];
+        for (name, attr, iso639_2_code, ietf) in cases {
+            let detected = detect(name, attr);
+            assert_eq!(iso639_2(name, attr), iso639_2_code, "{}", name);
+            assert_eq!(detected.map(|l| l.ietf()).unwrap_or_else(|| "und".to_string()), ietf, "{}", name);
+        }
+    }
+}
@@ -7,8 +7,30 @@ mod tui;
 mod downloader;
 mod ffmpeg;
 mod ui;
+mod cli;
+mod quality;
+mod naming;
+mod nfo;
+mod container;
+mod transcode;
+mod http;
+mod hls;
+mod dash;
+mod direct;
+mod subtitles;
+mod lang;
+mod fonts;
+mod chapters;
+mod metadata;
+mod source;
+mod formats;
+mod probe;
+mod disk;
+mod verify;
+mod archive;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
+use clap::Parser;
 use console::{style, Term};
 use dialoguer::{Confirm, Input, theme::ColorfulTheme};
 use ini::Ini;
@@ -19,8 +41,9 @@ use std::sync::Arc;
 use tokio::sync::Semaphore;
 
 use types::*;
-use parser::parse_m3u;
 use config::*;
+use cli::Cli;
+use source::PlaylistSource;
 
 const VERSION: &str = "2.0.0";
 
@@ -36,7 +59,45 @@ fn get_custom_theme() -> ColorfulTheme {
     }
 }
 
-async fn run_app(term: &Term) -> Result<()> {
+/// One playlist queued in a session, with its own output folder and settings.
+struct Job {
+    name: String,
+    links: Vec<LinkInfo>,
+    folder: PathBuf,
+    overrides: Option<Ini>,
+    settings: Settings,
+}
+
+/// Asks for playlists until the user is done. Entries may be files, folders, URLs or pasted text.
+fn prompt_sources(term: &Term) -> Result<Vec<PlaylistSource>> {
+    let mut sources = Vec::new();
+    loop {
+        let input: String = Input::new().with_prompt("Path or URL of your playlist (M3U, JSON, CSV or URL list), or a folder of them (empty to paste it)").allow_empty(true).interact_text_on(term)?;
+        let found = if input.trim().is_empty() { source::read_pasted(term).map(|pasted| vec![pasted]) } else { source::expand(&input) };
+        match found {
+            Ok(found) => sources.extend(found),
+            Err(e) => term.write_line(&format!("{}", style(e).red()))?,
+        }
+        if !sources.is_empty() && !Confirm::with_theme(&get_custom_theme()).with_prompt("Queue another playlist?").default(false).interact_on(term)? {
+            return Ok(sources);
+        }
+    }
+}
+
+fn apply_profile_override(settings: &mut Settings, cli: &Cli) -> Result<()> {
+    if let Some(profile) = &cli.profile {
+        if profile == transcode::COPY_PROFILE {
+            settings.transcode_profile = None;
+        } else if settings.profiles.iter().any(|p| p.name.eq_ignore_ascii_case(profile)) {
+            settings.transcode_profile = Some(profile.clone());
+        } else {
+            return Err(anyhow::anyhow!("Unknown transcode profile '{}'", profile));
+        }
+    }
+    Ok(())
+}
+
+async fn run_app(term: &Term, cli: &Cli) -> Result<()> {
     term.write_line(&format!("{} {}", style("M3U Batch Downloader for AniLINK").bold().blue(), style(format!("(v{})", VERSION)).dim()))?;
     
     let config_dir = get_config_dir()?;
@@ -55,27 +116,70 @@ async fn run_app(term: &Term) -> Result<()> {
     
     term.write_line(&format!("Using ffmpeg: {}", style(&ffmpeg_path).cyan()))?;
 
-    let file_path_str: String = Input::new().with_prompt("Path to your M3U file").interact_text_on(term)?;
-    let input = file_path_str.trim().trim_matches('"').trim_matches('\'');
-    let file_path: PathBuf = if input.starts_with('~') {
-        dirs_next::home_dir().map(|h| h.join(&input[1..])).unwrap_or_else(|| input.into())
-    } else {
-        input.into()
+    let sources = match cli.playlist.is_empty() {
+        true => prompt_sources(term)?,
+        false => cli.playlist.iter().map(|input| source::expand(input)).collect::<Result<Vec<_>>>()?.into_iter().flatten().collect(),
     };
 
-    let links = parse_m3u(&file_path).context("Failed to parse M3U file")?;
-    if links.is_empty() {
-        term.write_line(&format!("{}", style("No links found in M3U file.").red()))?;
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut next_id = 0;
+    for (idx, source) in sources.iter().enumerate() {
+        if sources.len() > 1 {
+            term.write_line(&format!("\n{}", style(format!("Playlist {}/{}: {}", idx + 1, sources.len(), source.label())).bold()))?;
+        }
+        let playlist = match source::load(source, &settings, cli.referer.as_deref()).await {
+            Ok(playlist) => playlist,
+            // One unreadable playlist shouldn't take the rest of the queue down with it
+            Err(e) if sources.len() > 1 => {
+                term.write_line(&format!("{}", style(format!("Skipping {}: {:#}", source.label(), e)).red()))?;
+                continue;
+            }
+            Err(e) => return Err(e.context("Failed to load M3U playlist")),
+        };
+        if !playlist.warnings.is_empty() {
+            term.write_line(&format!("\n{}", style(format!("{} problem(s) found in the playlist:", playlist.warnings.len())).bold().yellow()))?;
+            for warning in &playlist.warnings {
+                term.write_line(&format!("  {} {}", style("!").yellow(), warning))?;
+            }
+        }
+        let name = source::default_folder(source, &playlist);
+        let links = playlist.links;
+        if links.is_empty() {
+            term.write_line(&format!("{}", style("No links found in M3U file.").red()))?;
+            continue;
+        }
+
+        let mut links = if quality::has_multiple_sources(&links) {
+            let preference = match &cli.quality {
+                Some(preference) => preference.clone(),
+                None => ui::select_quality(term, &links)?,
+            };
+            quality::select_links(&links, &preference)
+        } else {
+            links
+        };
+        // Ids identify downloads in the shared state, so they must be unique across playlists
+        let id_count = links.iter().map(|l| l.id + 1).max().unwrap_or(0);
+        for link in &mut links {
+            link.id += next_id;
+            link.playlist = Some(name.clone());
+        }
+        next_id += id_count;
+
+        let folder_str: String = Input::with_theme(&get_custom_theme())
+            .with_prompt("Folder to save videos in")
+            .default(name.clone())
+            .interact_text_on(term)?;
+        let overrides = source::overrides(source)?;
+        if let Some((path, _)) = &overrides {
+            term.write_line(&format!("Using settings overrides from {}", style(path.display()).cyan()))?;
+        }
+        jobs.push(Job { name, links, folder: folder_str.into(), overrides: overrides.map(|(_, conf)| conf), settings: settings.clone() });
+    }
+    if jobs.is_empty() {
         return Ok(());
     }
 
-    let folder_str: String = Input::with_theme(&get_custom_theme())
-        .with_prompt("Folder to save videos in")
-        .default(file_path.file_stem().unwrap_or_default().to_string_lossy().to_string())
-        .interact_text_on(term)?;
-    let folder: PathBuf = folder_str.into();
-    fs::create_dir_all(&folder)?;
-
     if Confirm::with_theme(&get_custom_theme()).with_prompt("Customize settings?").default(false).interact_on(term)? {
         ui::customize(term, &mut settings)?;
         save_settings(&config_file, &settings)?;
@@ -86,55 +190,199 @@ async fn run_app(term: &Term) -> Result<()> {
         }
     }
 
-    let links_to_download = ui::check_existing(term, &links, &folder)?;
-    if links_to_download.is_empty() {
-        term.write_line(&format!("{}", style("No new files to download.").bold().green()))?;
+    apply_profile_override(&mut settings, cli)?;
+    if let Some(profile) = transcode::active_profile(&settings) {
+        term.write_line(&format!("Transcoding with profile: {}", style(&profile.name).cyan()))?;
+    }
+    for job in &mut jobs {
+        if let Some(overrides) = &job.overrides {
+            job.settings = apply_overrides(&settings, overrides);
+            apply_profile_override(&mut job.settings, cli)?;
+        } else {
+            job.settings = settings.clone();
+        }
+    }
+
+    if cli.dry_run {
+        for job in &jobs {
+            if jobs.len() > 1 {
+                term.write_line(&format!("\n{}", style(format!("== {} ==", job.name)).bold().blue()))?;
+            }
+            ui::print_dry_run(term, &job.links, &job.folder, &job.settings)?;
+        }
+        return Ok(());
+    }
+
+    if cli.check {
+        for job in &jobs {
+            if jobs.len() > 1 {
+                term.write_line(&format!("\n{}", style(format!("== {} ==", job.name)).bold().blue()))?;
+            }
+            term.write_line(&format!("Probing {} entries...", job.links.len()))?;
+            let probes = probe::probe_all(&job.links, &job.settings).await?;
+            ui::print_probes(term, &job.links, &probes)?;
+        }
         return Ok(());
     }
 
+    if cli.rebuild_archive {
+        term.write_line("Rebuilding the download archive...")?;
+        let folders: Vec<PathBuf> = jobs.iter().map(|job| job.folder.clone()).collect();
+        let (kept, added, untagged) = archive::rebuild(&settings, &folders).await?;
+        term.write_line(&format!("Archive rebuilt: {} entries kept, {} added from file tags", kept, added))?;
+        if untagged > 0 {
+            term.write_line(&format!("{}", style(format!(
+                "{} files without a source_url tag were skipped, e.g. direct downloads that needed no muxing.", untagged
+            )).dim()))?;
+        }
+    }
+    let archive = if cli.ignore_archive { None } else { Some(archive::Archive::load()?) };
+
+    let mut queue: Vec<(usize, LinkInfo)> = Vec::new();
+    // Estimated bytes queued per folder, and how many entries have no estimate
+    let mut estimates: Vec<(PathBuf, u64)> = Vec::new();
+    let mut unestimated = 0;
+    for (job_idx, job) in jobs.iter().enumerate() {
+        fs::create_dir_all(&job.folder)?;
+        if jobs.len() > 1 {
+            term.write_line(&format!("\n{}", style(format!("== {} ==", job.name)).bold().blue()))?;
+        }
+        let links = match &archive {
+            Some(archive) => ui::skip_archived(term, &job.links, archive)?,
+            None => job.links.clone(),
+        };
+        let links_to_download = ui::check_existing(term, &links, &job.links, &job.folder, &job.settings)?;
+        if links_to_download.is_empty() {
+            term.write_line(&format!("{}", style("No new files to download.").bold().green()))?;
+            continue;
+        }
+
+        let warnings = container::validate(job.settings.container, job.settings.sidecar_subtitles, &links_to_download);
+        if !warnings.is_empty() {
+            term.write_line(&format!("\n{}", style(format!("The {} container can't hold everything selected:", job.settings.container)).bold().yellow()))?;
+            for warning in &warnings {
+                term.write_line(&format!("  {} {}", style("!").yellow(), warning))?;
+            }
+            if !Confirm::with_theme(&get_custom_theme()).with_prompt("Continue anyway?").default(true).interact_on(term)? {
+                continue;
+            }
+        }
+
+        let links_to_download = if job.settings.probe_links {
+            term.write_line(&format!("Probing {} entries...", links_to_download.len()))?;
+            let probes = probe::probe_all(&links_to_download, &job.settings).await?;
+            ui::print_probes(term, &links_to_download, &probes)?;
+            let mut links = links_to_download;
+            // A probed duration lets the progress bar work for streams that don't report one
+            for (link, probe) in links.iter_mut().zip(&probes) {
+                link.duration = link.duration.or(probe.duration);
+                link.probed_duration = probe.duration;
+            }
+            let selected = ui::select_probed(term, &links, &probes)?;
+            let sizes: Vec<Option<u64>> = selected.iter()
+                .map(|s| links.iter().zip(&probes).find(|(l, _)| l.id == s.id).and_then(|(_, p)| p.size))
+                .collect();
+            estimates.push((job.folder.clone(), sizes.iter().flatten().sum()));
+            unestimated += sizes.iter().filter(|s| s.is_none()).count();
+            selected
+        } else {
+            estimates.push((job.folder.clone(), 0));
+            unestimated += links_to_download.len();
+            links_to_download
+        };
+        queue.extend(links_to_download.into_iter().map(|link| (job_idx, link)));
+    }
+    if queue.is_empty() {
+        return Ok(());
+    }
+
+    let threshold = settings.min_free_space_mb * disk::MB;
+    let shortages = disk::check_space(&estimates, threshold);
+    if !shortages.is_empty() {
+        term.write_line(&format!("\n{}", style("There may not be enough disk space for this batch:").bold().yellow()))?;
+        for shortage in &shortages {
+            let folders = shortage.folders.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join(", ");
+            term.write_line(&format!("  {} {}: needs about {} MB including the {} MB to keep free, {} MB free",
+                style("!").yellow(), folders, shortage.needed / disk::MB, settings.min_free_space_mb, shortage.free / disk::MB))?;
+        }
+        if unestimated > 0 {
+            term.write_line(&format!("{}", style(format!("{} entries have no size estimate and aren't counted.", unestimated)).dim()))?;
+        }
+        if !Confirm::with_theme(&get_custom_theme()).with_prompt("Start anyway?").default(false).interact_on(term)? {
+            return Ok(());
+        }
+    }
+
     term.write_line(&format!("\n{}\n", style("Press Shift+Q to exit...").bold()))?;
 
     let downloads_state: Arc<Mutex<Vec<(LinkInfo, DownloadStatus)>>> = Arc::new(Mutex::new(
-        links_to_download.iter().map(|li| (li.clone(), DownloadStatus::Pending)).collect()
+        queue.iter().map(|(_, li)| (li.clone(), DownloadStatus::Pending)).collect()
     ));
 
+    let notice: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
     let tui_handle = tokio::spawn({
-        let state = downloads_state.clone();
-        async move { tui::run_tui(tui::DownloadTUI::new_with_state(state)) }
+        let (state, notice) = (downloads_state.clone(), notice.clone());
+        async move { tui::run_tui(tui::DownloadTUI::new_with_state(state, notice)) }
+    });
+    let space_monitor = tokio::spawn({
+        let notice = notice.clone();
+        let folders: Vec<PathBuf> = jobs.iter().map(|job| job.folder.clone()).collect();
+        async move {
+            loop {
+                *notice.lock() = disk::low_space_notice(&folders, threshold);
+                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            }
+        }
     });
 
+    // Per-playlist overrides don't change how many downloads run at once across the session
     let semaphore = Arc::new(Semaphore::new(settings.parallel_downloads));
     let mut tasks = Vec::new();
+    let outputs: Vec<_> = jobs.iter().map(|job| utils::output_files(&job.links, &job.folder, &job.settings)).collect();
 
-    for link_info in links_to_download {
+    for (job_idx, link_info) in queue {
         let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let (settings_clone, folder_clone, all_links, state, link_id) = 
-            (settings.clone(), folder.clone(), links.clone(), downloads_state.clone(), link_info.id);
+        let job = &jobs[job_idx];
+        // New downloads wait while the disk is nearly full, running ones carry on
+        while disk::low_space(&job.folder, threshold) && !tui_handle.is_finished() {
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        }
+        let (output_file, settings_clone, state, link_id) =
+            (outputs[job_idx][&link_info.id].clone(), job.settings.clone(), downloads_state.clone(), link_info.id);
 
         tasks.push(tokio::spawn(async move {
-            let result = downloader::download_stream(link_info, folder_clone, settings_clone, all_links, state, link_id).await;
+            let result = downloader::download_stream(link_info, output_file, settings_clone, state, link_id).await;
             drop(permit);
             result
         }));
     }
 
     for task in tasks { let _ = task.await; }
+    space_monitor.abort();
     tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
     let _ = tui_handle.await;
 
+    for (link_info, _) in downloads_state.lock().iter() {
+        for warning in link_info.warnings.lock().iter() {
+            term.write_line(&format!("{} {}: {}", style("!").yellow(), link_info.name, warning))?;
+        }
+    }
+
     term.write_line(&format!("\n{}", style("All downloads completed!").bold().green()))?;
     Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
     let term = Arc::new(Term::stdout());
     loop {
         let _ = term.clear_screen();
-        if let Err(e) = run_app(&term).await {
+        if let Err(e) = run_app(&term, &cli).await {
             let _ = term.write_line(&format!("\n{}\n", style(format!("An unexpected error occurred: {}", e)).red()));
         }
-        if !Confirm::with_theme(&get_custom_theme()).with_prompt("\n\nProcess another M3U file?").default(false).interact_on(&term).unwrap_or(false) {
+        // Playlists given on the command line make this a one-shot run
+        if !cli.playlist.is_empty() || !Confirm::with_theme(&get_custom_theme()).with_prompt("\n\nProcess another M3U file?").default(false).interact_on(&term).unwrap_or(false) {
             break;
         }
     }
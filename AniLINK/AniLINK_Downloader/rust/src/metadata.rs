@@ -0,0 +1,63 @@
+use chrono::Local;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+use crate::archive::normalize_url;
+use crate::lang;
+use crate::quality::quality_label;
+use crate::types::LinkInfo;
+
+lazy_static! {
+    static ref DUB_RE: Regex = Regex::new(r"(?i)\bdub(bed)?\b").unwrap();
+}
+
+/// What sets a dubbed release or one with extra audio tracks apart from the plain one, e.g.
+/// "dub+eng": "dub" when the name or quality says so, then the language of each muxed audio
+/// track. `None` for the usual subbed release. Tagged so the archive can tell releases apart.
+pub fn audio_variant(link_info: &LinkInfo) -> Option<String> {
+    let dub = DUB_RE.is_match(&link_info.name) || link_info.quality.as_deref().is_some_and(|q| DUB_RE.is_match(q));
+    let parts: Vec<&str> = dub.then_some("dub").into_iter()
+        .chain(link_info.audio.iter().map(|track| lang::iso639_2(&track.name, track.language.as_deref())))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("+"))
+}
+
+/// Container-level tags that let a file be traced back to where it was downloaded from.
+pub fn tags(link_info: &LinkInfo) -> Vec<(&'static str, String)> {
+    let mut tags = vec![("title", link_info.name.clone())];
+    if let Some(show) = &link_info.show {
+        tags.push(("show", show.clone()));
+    }
+    if let Some(season) = link_info.season.or(link_info.episode.map(|_| 1)) {
+        tags.push(("season_number", season.to_string()));
+    }
+    if let Some(episode) = link_info.episode {
+        tags.push(("episode_sort", episode.to_string()));
+        tags.push(("episode_id", episode.to_string()));
+    }
+    if let Some(quality) = &link_info.quality {
+        tags.push(("quality", quality_label(quality).to_string()));
+    }
+    if let Some(audio) = audio_variant(link_info) {
+        tags.push(("audio_variant", audio));
+    }
+    // Signed CDN links carry session tokens that shouldn't travel with a shared file
+    tags.push(("source_url", normalize_url(&link_info.url)));
+    if let Some(host) = link_info.referer.as_deref().and_then(|r| Url::parse(r).ok()).and_then(|u| u.host_str().map(String::from)) {
+        tags.push(("referer_host", host));
+    }
+    tags.push(("download_date", Local::now().format("%Y-%m-%d").to_string()));
+    tags.push(("encoded_by", format!("AniLINK Downloader v{}", crate::VERSION)));
+    tags
+}
+
+pub fn image_mimetype(content_type: Option<&str>, url: &str) -> (&'static str, &'static str) {
+    let lower = url.to_lowercase();
+    match content_type.unwrap_or_default() {
+        t if t.contains("png") => ("image/png", "png"),
+        t if t.contains("webp") => ("image/webp", "webp"),
+        _ if lower.contains(".png") => ("image/png", "png"),
+        _ if lower.contains(".webp") => ("image/webp", "webp"),
+        _ => ("image/jpeg", "jpg"),
+    }
+}
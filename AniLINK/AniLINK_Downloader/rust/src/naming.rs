@@ -0,0 +1,171 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use sanitize_filename::sanitize;
+use crate::quality::quality_label;
+use crate::types::LinkInfo;
+
+pub const DEFAULT_TEMPLATE: &str = "{name}.{ext}";
+const SEASON_FOLDERS: &str = "{show}/Season {season:02}/";
+const SHOW_FOLDER: &str = "{show}/";
+
+lazy_static! {
+    static ref SXXEXX_RE: Regex = Regex::new(r"(?i)\bS(\d{1,2})\s*E(\d{1,4})\b").unwrap();
+    static ref SEASON_RE: Regex = Regex::new(r"(?i)\bSeason\s*(\d{1,2})\b|\b(\d{1,2})(?:st|nd|rd|th)\s+Season\b").unwrap();
+    static ref EPISODE_RE: Regex = Regex::new(r"(?i)\b(?:Episode|Ep\.?|E)\s*(\d{1,4})\b").unwrap();
+    // A bare trailing number is too often part of the title ("Blade Runner 2049"), so it only
+    // counts as the episode after a "-" or "#"
+    static ref TRAILING_NUM_RE: Regex = Regex::new(r"(?:\s+-\s*|\s*#)(\d{1,4})$").unwrap();
+    static ref PLACEHOLDER_RE: Regex = Regex::new(r"\{([\w-]+)(?::0(\d))?\}").unwrap();
+    static ref EMPTY_BRACKETS_RE: Regex = Regex::new(r"\[\s*\]|\(\s*\)").unwrap();
+    static ref SPACES_RE: Regex = Regex::new(r"\s{2,}").unwrap();
+    static ref SPACE_BEFORE_EXT_RE: Regex = Regex::new(r"\s+(\.\w+)$").unwrap();
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EpisodeInfo {
+    pub show: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+}
+
+/// Extracts show title, season and episode numbers from names such as
+/// "Show Name - Episode 12", "Show S02E05" or "Show 2nd Season - 03".
+pub fn parse_episode_info(name: &str) -> EpisodeInfo {
+    let name = name.trim();
+    let mut info = EpisodeInfo::default();
+    let mut show_end = name.len();
+
+    if let Some(c) = SXXEXX_RE.captures(name) {
+        info.season = c[1].parse().ok();
+        info.episode = c[2].parse().ok();
+        show_end = c.get(0).unwrap().start();
+    } else {
+        if let Some(c) = SEASON_RE.captures(name) {
+            info.season = c.get(1).or(c.get(2)).and_then(|m| m.as_str().parse().ok());
+            show_end = show_end.min(c.get(0).unwrap().start());
+        }
+        if let Some(c) = EPISODE_RE.captures(name).or_else(|| TRAILING_NUM_RE.captures(name)) {
+            info.episode = c[1].parse().ok();
+            show_end = show_end.min(c.get(0).unwrap().start());
+        }
+    }
+
+    let show = name[..show_end].trim_matches(|c: char| c.is_whitespace() || "-_.:|#".contains(c));
+    if !show.is_empty() && show_end < name.len() {
+        info.show = Some(show.to_string());
+    }
+    info
+}
+
+fn placeholder_value(link_info: &LinkInfo, key: &str, ext: &str) -> Option<String> {
+    match key {
+        "name" => Some(link_info.name.clone()),
+        "show" => Some(link_info.show.clone().unwrap_or_else(|| link_info.name.clone())),
+        // Media servers treat files without a season as season 1
+        "season" => link_info.season.or(link_info.episode.map(|_| 1)).map(|s| s.to_string()),
+        // The EXTINF group-title, else the #EXTGRP name, which older AniLINK playlists set to the quality
+        "group" => link_info.group.clone().or_else(|| link_info.show.clone()),
+        "episode" => link_info.episode.map(|e| e.to_string()),
+        "quality" => link_info.quality.as_deref().map(|q| quality_label(q).to_string()),
+        "ext" => Some(ext.to_string()),
+        // Any other EXTINF attribute, e.g. {tvg-id}
+        _ => link_info.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()),
+    }
+}
+
+fn render_component(component: &str, link_info: &LinkInfo, ext: &str) -> String {
+    let rendered = PLACEHOLDER_RE.replace_all(component, |c: &Captures| {
+        let value = placeholder_value(link_info, &c[1], ext).unwrap_or_default();
+        match (c.get(2).and_then(|w| w.as_str().parse::<usize>().ok()), value.parse::<u32>()) {
+            (Some(width), Ok(n)) => format!("{:0width$}", n, width = width),
+            // Values can't introduce extra folders, only the template can
+            _ => value.replace(['/', '\\'], "-"),
+        }
+    });
+    let cleaned = EMPTY_BRACKETS_RE.replace_all(&rendered, "");
+    let cleaned = SPACES_RE.replace_all(&cleaned, " ");
+    let cleaned = SPACE_BEFORE_EXT_RE.replace(&cleaned, "$1");
+    sanitize(cleaned.trim_matches(|c: char| c.is_whitespace() || c == '-'))
+}
+
+/// Renders a naming template into a relative path. Each `/`-separated part of the template
+/// becomes a folder, `{key}` is substituted and `{key:02}` zero-pads numbers.
+pub fn render_template(template: &str, link_info: &LinkInfo, ext: &str) -> Vec<String> {
+    let template = if template.trim().is_empty() { DEFAULT_TEMPLATE } else { template };
+    template
+        .split(['/', '\\'])
+        .map(|component| render_component(component, link_info, ext))
+        .filter(|component| !component.is_empty())
+        .collect()
+}
+
+/// Folders a naming template ends up with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// Whatever folders the template defines itself, if any.
+    Template,
+    Show,
+    ShowSeason,
+}
+
+/// Applies the Show/Season NN layout to templates that don't define folders of their own, and
+/// says which layout was used. Entries without a season or episode number (e.g. movies) only
+/// get the show folder.
+pub fn layout_template(template: &str, season_folders: bool, link_info: &LinkInfo) -> (String, Layout) {
+    let template = if template.trim().is_empty() { DEFAULT_TEMPLATE } else { template };
+    if !season_folders || template.contains(['/', '\\']) {
+        return (template.to_string(), Layout::Template);
+    }
+    if link_info.season.is_some() || link_info.episode.is_some() {
+        (format!("{}{}", SEASON_FOLDERS, template), Layout::ShowSeason)
+    } else {
+        (format!("{}{}", SHOW_FOLDER, template), Layout::Show)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::new_link;
+
+    fn info(show: Option<&str>, season: Option<u32>, episode: Option<u32>) -> EpisodeInfo {
+        EpisodeInfo { show: show.map(String::from), season, episode }
+    }
+
+    #[test]
+    fn episode_info_from_common_name_styles() {
+        let cases = [
+            ("Show Name - Episode 12", info(Some("Show Name"), None, Some(12))),
+            ("Show Name S02E05", info(Some("Show Name"), Some(2), Some(5))),
+            ("Show Name s1 e3 - Title", info(Some("Show Name"), Some(1), Some(3))),
+            ("Show Name 2nd Season - 03", info(Some("Show Name"), Some(2), Some(3))),
+            ("Show Name Season 3 Ep. 7", info(Some("Show Name"), Some(3), Some(7))),
+            ("Show Name #04", info(Some("Show Name"), None, Some(4))),
+            ("Movie Title", info(None, None, None)),
+            ("Show Name - 05", info(Some("Show Name"), None, Some(5))),
+            ("Blade Runner 2049", info(None, None, None)),
+            ("Mob Psycho 100", info(None, None, None)),
+            ("Mob Psycho 100 - 05", info(Some("Mob Psycho 100"), None, Some(5))),
+            ("Mob Psycho 100 Episode 5", info(Some("Mob Psycho 100"), None, Some(5))),
+            ("Mob Psycho 100 #5", info(Some("Mob Psycho 100"), None, Some(5))),
+            ("Show_Name_05", info(None, None, None)),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(parse_episode_info(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn templates_pad_numbers_and_split_folders() {
+        let link = new_link(0, "Show Name S02E05 [1080p]", "https://cdn/1.m3u8".to_string());
+        let parts = render_template("{show}/Season {season:02}/{show} - S{season:02}E{episode:02} [{quality}].{ext}", &link, "mkv");
+        assert_eq!(parts, ["Show Name", "Season 02", "Show Name - S02E05 [1080p].mkv"]);
+    }
+
+    #[test]
+    fn templates_drop_empty_placeholders_and_keep_values_in_one_folder() {
+        let link = new_link(0, "AC/DC Live", "https://cdn/1.mp4".to_string());
+        assert_eq!(render_template("{name} [{quality}].{ext}", &link, "mp4"), ["AC-DC Live.mp4"]);
+        assert_eq!(render_template("", &link, "mkv"), ["AC-DC Live.mkv"]);
+    }
+}
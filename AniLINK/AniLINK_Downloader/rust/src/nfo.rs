@@ -0,0 +1,58 @@
+use anyhow::Result;
+use chrono::Local;
+use std::fs;
+use std::path::Path;
+use crate::naming::Layout;
+use crate::quality::quality_label;
+use crate::types::LinkInfo;
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn tag(name: &str, value: impl ToString) -> String {
+    format!("  <{name}>{}</{name}>\n", escape_xml(&value.to_string()))
+}
+
+/// Writes Kodi/Jellyfin style metadata next to a finished download: an episode `.nfo` beside
+/// the video and a `tvshow.nfo` in the show folder if one isn't there yet. `layout` is the one
+/// the output path was rendered with, which says where the show folder is.
+pub fn write_nfo(link_info: &LinkInfo, output_file: &Path, layout: Layout) -> Result<()> {
+    let show = link_info.show.as_deref().unwrap_or(&link_info.name);
+
+    let mut episode = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<episodedetails>\n");
+    episode += &tag("title", &link_info.name);
+    episode += &tag("showtitle", show);
+    if let Some(season) = link_info.season.or(link_info.episode.map(|_| 1)) {
+        episode += &tag("season", season);
+    }
+    if let Some(number) = link_info.episode {
+        episode += &tag("episode", number);
+    }
+    episode += &tag("dateadded", Local::now().format("%Y-%m-%d %H:%M:%S"));
+    episode += &tag("sourceurl", &link_info.url);
+    if let Some(quality) = &link_info.quality {
+        episode += &tag("quality", quality_label(quality));
+    }
+    episode += "</episodedetails>\n";
+    fs::write(output_file.with_extension("nfo"), episode)?;
+
+    let episode_dir = output_file.parent().unwrap_or(Path::new("."));
+    let show_dir = match (layout, episode_dir.parent()) {
+        (Layout::ShowSeason, Some(parent)) => parent,
+        _ => episode_dir,
+    };
+    let tvshow_nfo = show_dir.join("tvshow.nfo");
+    if !tvshow_nfo.exists() {
+        let mut tvshow = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<tvshow>\n");
+        tvshow += &tag("title", show);
+        tvshow += &tag("dateadded", Local::now().format("%Y-%m-%d %H:%M:%S"));
+        tvshow += "</tvshow>\n";
+        fs::write(tvshow_nfo, tvshow)?;
+    }
+    Ok(())
+}
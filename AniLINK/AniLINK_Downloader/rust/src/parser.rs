@@ -1,81 +1,352 @@
 use anyhow::Result;
+use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::BTreeSet;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fmt;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use parking_lot::Mutex;
-use crate::types::{LinkInfo, Subtitle};
+use crate::chapters::parse_chapters;
+use crate::hls::{parse_attributes, resolve_url};
+use crate::naming::parse_episode_info;
+use crate::types::{Chapter, LinkInfo, Subtitle};
 
-pub fn parse_m3u(file_path: &Path) -> Result<Vec<LinkInfo>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+lazy_static! {
+    static ref ATTRIBUTE_RE: Regex = Regex::new(r#"([\w-]+)=(?:"([^"]*)"|([^\s"]+))"#).unwrap();
+}
 
-    let mut links = Vec::new();
-    let mut referer: Option<String> = None;
-    let mut subtitles: Vec<Subtitle> = Vec::new();
+/// The parts of an `#EXTINF:<duration> key="value"...,<title>` line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtInf {
+    pub duration: Option<f64>,
+    pub attributes: Vec<(String, String)>,
+    pub title: String,
+}
+
+impl ExtInf {
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// Parses what follows `#EXTINF:`. The title starts after the first comma outside quotes,
+/// so attribute values and titles may both contain commas. A duration of -1 means unknown.
+pub fn parse_extinf(info: &str) -> ExtInf {
+    let mut in_quotes = false;
+    let split = info.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        c == ',' && !in_quotes
+    });
+    let (head, title) = match split {
+        Some((i, _)) => (&info[..i], info[i + 1..].trim()),
+        None => (info, ""),
+    };
+
+    let head = head.trim();
+    let duration_end = head.find(char::is_whitespace).unwrap_or(head.len());
+    let duration = head[..duration_end].parse::<f64>().ok().filter(|d| *d > 0.0);
+    let attributes = ATTRIBUTE_RE.captures_iter(&head[duration_end..])
+        .map(|c| (c[1].to_lowercase(), c.get(2).or(c.get(3)).map_or("", |m| m.as_str()).to_string()))
+        .collect();
+
+    ExtInf { duration, attributes, title: title.to_string() }
+}
 
-    let name_re = Regex::new(r#"NAME="([^"]+)""#).unwrap();
-    let uri_re = Regex::new(r#"URI="([^"]+)""#).unwrap();
+/// Request options from `#EXTVLCOPT` and `#EXTHTTP` lines that apply at one scope.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct HeaderScope {
+    referer: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+impl HeaderScope {
+    fn is_empty(&self) -> bool {
+        self.referer.is_none() && self.headers.is_empty()
+    }
+
+    /// Sets a header, replacing an earlier value for the same name.
+    fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Layers a narrower scope on top of this one, its values win.
+    fn merge(&self, other: &HeaderScope) -> HeaderScope {
+        let mut merged = self.clone();
+        if other.referer.is_some() {
+            merged.referer = other.referer.clone();
+        }
+        for (name, value) in &other.headers {
+            merged.set_header(name, value);
+        }
+        merged
+    }
 
-    for (i, line) in lines.iter().enumerate() {
+    /// Records a header option line. Returns `Ok(false)` for lines that aren't one and an
+    /// error for option lines that can't be read.
+    fn apply(&mut self, line: &str) -> Result<bool, String> {
         if let Some(r) = line.strip_prefix("#EXTVLCOPT:http-referrer=") {
-            referer = Some(r.to_string());
-        } else if line.starts_with("#EXT-X-MEDIA:TYPE=SUBTITLES") {
-            let name = name_re
-                .captures(line)
-                .and_then(|c| c.get(1))
-                .map(|m| m.as_str().to_string())
-                .unwrap_or_else(|| "Subtitle".to_string());
-            if let Some(uri_cap) = uri_re.captures(line) {
-                if let Some(url) = uri_cap.get(1) {
-                    subtitles.push(Subtitle {
-                        name,
-                        url: url.as_str().to_string(),
-                        default: line.contains("DEFAULT=YES"),
-                    });
+            self.referer = Some(r.to_string());
+        } else if let Some(ua) = line.strip_prefix("#EXTVLCOPT:http-user-agent=") {
+            self.set_header("User-Agent", ua);
+        } else if let Some(origin) = line.strip_prefix("#EXTVLCOPT:http-origin=") {
+            self.set_header("Origin", origin);
+        } else if let Some(cookie) = line.strip_prefix("#EXTVLCOPT:http-cookie=") {
+            self.set_header("Cookie", cookie);
+        } else if let Some(header) = line.strip_prefix("#EXTVLCOPT:http-header=") {
+            let (name, value) = header.split_once(':').ok_or("http-header option should look like `Name: value`")?;
+            self.set_header(name.trim(), value.trim());
+        } else if let Some(json) = line.strip_prefix("#EXTHTTP:") {
+            // e.g. #EXTHTTP:{"cookie":"a=b","user-agent":"..."}
+            let Ok(serde_json::Value::Object(map)) = serde_json::from_str(json) else {
+                return Err("#EXTHTTP should hold a JSON object".to_string());
+            };
+            for (name, value) in map.iter().filter_map(|(k, v)| Some((k, v.as_str()?))) {
+                match name.to_lowercase().as_str() {
+                    "referer" | "referrer" => self.referer = Some(value.to_string()),
+                    _ => self.set_header(name, value),
                 }
             }
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+/// Something in a playlist that was skipped or guessed, with its 1-based line number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseWarning {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Playlist {
+    /// From `#PLAYLIST:` or a `title` attribute on `#EXTM3U`.
+    pub title: Option<String>,
+    pub links: Vec<LinkInfo>,
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Builds a link from its display name, splitting off a `[quality]` suffix and reading the
+/// show, season and episode from what's left. Everything else starts out empty.
+pub fn new_link(id: usize, name: &str, url: String) -> LinkInfo {
+    let mut parsed_name = name.to_string();
+    if let Some(stripped) = parsed_name.strip_suffix(".mp4") {
+        parsed_name = stripped.to_string();
+    } else if let Some(stripped) = parsed_name.strip_suffix(".m3u8") {
+        parsed_name = stripped.to_string();
+    }
+    let mut quality = None;
+    if let (Some(start), Some(end)) = (name.rfind('['), name.rfind(']')) {
+        if start < end {
+            quality = Some(name[start..=end].to_string());
+            parsed_name = name[..start].trim().to_string();
+        }
+    }
+    let episode_info = parse_episode_info(&parsed_name);
+    LinkInfo {
+        id,
+        name: parsed_name,
+        url,
+        referer: None,
+        headers: Vec::new(),
+        subtitles: Vec::new(),
+        audio: Vec::new(),
+        quality,
+        show: episode_info.show,
+        season: episode_info.season,
+        episode: episode_info.episode,
+        chapters: Vec::new(),
+        logo: None,
+        group: None,
+        duration: None,
+        probed_duration: None,
+        attributes: Vec::new(),
+        playlist: None,
+        process_id: Arc::new(Mutex::new(None)),
+        paused: Arc::new(AtomicBool::new(false)),
+        warnings: Arc::new(Mutex::new(Vec::new())),
+        subtitle_status: Arc::new(Mutex::new(Vec::new())),
+    }
+}
+
+/// Resolves a URI against where the playlist came from, a URL or a local file.
+pub fn resolve_uri(base: Option<&str>, uri: &str) -> String {
+    let is_absolute = uri.contains("://") || Path::new(uri).is_absolute();
+    match base {
+        Some(base) if !is_absolute && base.contains("://") => resolve_url(base, uri),
+        Some(base) if !is_absolute => Path::new(base).parent().unwrap_or(Path::new("")).join(uri).to_string_lossy().to_string(),
+        _ => uri.to_string(),
+    }
+}
+
+/// Name for an entry without `#EXTINF`, taken from the last path segment of its URL.
+pub fn name_from_uri(uri: &str) -> Option<String> {
+    let path = uri.split(['?', '#']).next()?.trim_end_matches('/');
+    let file = path.rsplit(['/', '\\']).next()?;
+    let stem = file.rsplit_once('.').map_or(file, |(stem, _)| stem);
+    (!stem.is_empty()).then(|| stem.to_string())
+}
+
+/// Parses playlist text into links. `base` is the playlist's URL or file path, used to resolve
+/// relative URIs. Blank lines and unknown tags may appear anywhere, including between an
+/// `#EXTINF` and its URL.
+///
+/// Header options are scoped as follows:
+/// - options before the first `#EXTINF` or `#EXTGRP` are playlist-wide defaults
+/// - `#EXTGRP:<name>` starts a group; options right before or after it (up to its first
+///   `#EXTINF`) apply to the whole group. Without `#EXTGRP`, options between two entries
+///   start a new unnamed group, which is how older AniLINK playlists mark each quality
+/// - options between an `#EXTINF` and its URL apply to that entry only
+///
+/// Narrower scopes override wider ones header by header.
+pub fn parse_playlist(content: &str, base: Option<&str>) -> Playlist {
+    let mut links = Vec::new();
+    let mut warnings = Vec::new();
+    let mut subtitles: Vec<Subtitle> = Vec::new();
+    let mut chapters: Vec<Chapter> = Vec::new();
+    let mut unknown_tags: Vec<String> = Vec::new();
+    let mut title: Option<String> = None;
+
+    let mut playlist = HeaderScope::default();
+    let mut group = HeaderScope::default();
+    let mut group_name: Option<String> = None;
+    // Options seen since the last entry, not yet assigned to a scope
+    let mut pending = HeaderScope::default();
+    let mut group_open = false;
+    let mut started = false;
+    // An #EXTINF waiting for its URL, with its line number and its own options
+    let mut entry: Option<(ExtInf, usize, HeaderScope)> = None;
+
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    for (idx, raw_line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = raw_line.trim();
+        let mut warn = |message: String| warnings.push(ParseWarning { line: line_no, message });
+        if line.is_empty() {
+            continue;
+        }
+
+        let scope = match &mut entry {
+            Some((_, _, scope)) => scope,
+            None => &mut pending,
+        };
+        match scope.apply(line) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => {
+                warn(format!("{}, ignored", e));
+                continue;
+            }
+        }
+
+        if let Some(header) = line.strip_prefix("#EXTM3U") {
+            let header = parse_extinf(&format!("0 {}", header));
+            title = title.or_else(|| header.attribute("title").map(String::from));
+        } else if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            title = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attributes = parse_attributes(attributes);
+            match (attributes.get("TYPE").map(String::as_str), attributes.get("URI")) {
+                (Some("SUBTITLES"), Some(uri)) => subtitles.push(Subtitle {
+                    name: attributes.get("NAME").cloned().unwrap_or_else(|| "Subtitle".to_string()),
+                    url: resolve_uri(base, uri),
+                    default: attributes.get("DEFAULT").is_some_and(|d| d == "YES"),
+                    language: attributes.get("LANGUAGE").cloned(),
+                }),
+                (Some("SUBTITLES"), None) => warn("subtitle track without a URI, ignored".to_string()),
+                // Audio renditions, which the userscript writes for dubs, are left to the stream itself
+                _ => {}
+            }
+        } else if let Some(list) = line
+            .strip_prefix("#EXT-X-ANILINK-CHAPTERS:")
+            .or_else(|| line.strip_prefix("#EXTVLCOPT:anilink-chapters="))
+        {
+            // Like subtitles, chapters belong to the next entry
+            chapters = parse_chapters(list);
+            if chapters.is_empty() {
+                warn("no valid chapters in the chapter list, ignored".to_string());
+            }
+        } else if let Some(name) = line.strip_prefix("#EXTGRP:") {
+            if started {
+                group = std::mem::take(&mut pending);
+            } else {
+                playlist = std::mem::take(&mut pending);
+            }
+            group_name = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+            group_open = true;
+            started = true;
         } else if let Some(info) = line.strip_prefix("#EXTINF:") {
-            let name = info
-                .split(',')
-                .nth(1)
-                .unwrap_or(&format!("Episode {}", links.len() + 1))
-                .to_string();
-            if let Some(url) = lines.get(i + 1) {
-                if !url.starts_with('#') {
-                    let mut parsed_name = name.clone();
-                    if let Some(stripped) = parsed_name.strip_suffix(".mp4") {
-                        parsed_name = stripped.to_string();
-                    } else if let Some(stripped) = parsed_name.strip_suffix(".m3u8") {
-                        parsed_name = stripped.to_string();
-                    }
-                    let mut quality = None;
-                    if let (Some(start), Some(end)) = (name.rfind('['), name.rfind(']')) {
-                        if start < end {
-                            quality = Some(name[start..=end].to_string());
-                            parsed_name = name[..start].trim().to_string();
-                        }
-                    }
-                    links.push(LinkInfo {
-                        id: links.len(),
-                        name: parsed_name,
-                        url: url.clone(),
-                        referer: referer.clone(),
-                        subtitles: subtitles.clone(),
-                        quality,
-                        process_id: Arc::new(Mutex::new(None)),
-                        paused: Arc::new(AtomicBool::new(false)),
-                    });
-                    subtitles.clear();
-                }
+            if let Some((skipped, skipped_line, _)) = entry.take() {
+                warnings.push(ParseWarning { line: skipped_line, message: format!("'{}' has no URL, skipped", skipped.title) });
+            }
+            let options = std::mem::take(&mut pending);
+            if !started {
+                playlist = options;
+            } else if group_open {
+                group = group.merge(&options);
+            } else if !options.is_empty() {
+                group = options;
+            }
+            group_open = false;
+            started = true;
+            entry = Some((parse_extinf(info), line_no, HeaderScope::default()));
+        } else if line.starts_with('#') {
+            // Plain comments and VLC options we have no use for are fine, other tags are worth
+            // mentioning once
+            let tag = line.split(':').next().unwrap_or(line);
+            if tag.starts_with("#EXT") && tag != "#EXTVLCOPT" && !unknown_tags.iter().any(|t| t == tag) {
+                unknown_tags.push(tag.to_string());
+                warn(format!("unknown tag {}, ignored", tag));
             }
+        } else {
+            let url = resolve_uri(base, line);
+            let (extinf, entry_options) = match entry.take() {
+                Some((extinf, _, options)) => (extinf, options),
+                None => {
+                    let extinf = ExtInf { title: name_from_uri(line).unwrap_or_default(), ..Default::default() };
+                    warn(format!("URL without #EXTINF, named '{}'", extinf.title));
+                    (extinf, HeaderScope::default())
+                }
+            };
+            let options = playlist.merge(&group).merge(&entry_options);
+            let name = match extinf.title.as_str() {
+                "" => extinf.attribute("tvg-name").map(String::from).unwrap_or_else(|| format!("Episode {}", links.len() + 1)),
+                title => title.to_string(),
+            };
+            let mut link = new_link(links.len(), &name, url);
+            let group_title = extinf.attribute("group-title").map(String::from);
+            link.referer = options.referer;
+            link.headers = options.headers;
+            link.subtitles = std::mem::take(&mut subtitles);
+            // Playlists often group episodes under the show's title
+            link.show = link.show.or_else(|| group_title.clone());
+            link.chapters = std::mem::take(&mut chapters);
+            link.logo = extinf.attribute("tvg-logo").map(|logo| resolve_uri(base, logo));
+            link.group = group_title.or_else(|| group_name.clone());
+            link.duration = extinf.duration;
+            link.attributes = extinf.attributes;
+            links.push(link);
         }
     }
-    Ok(links)
+    if let Some((skipped, skipped_line, _)) = entry {
+        warnings.push(ParseWarning { line: skipped_line, message: format!("'{}' has no URL, skipped", skipped.title) });
+    }
+    Playlist { title, links, warnings }
 }
 
 pub fn parse_number_ranges(s: &str) -> BTreeSet<usize> {
@@ -98,6 +369,16 @@ pub fn parse_number_ranges(s: &str) -> BTreeSet<usize> {
     result
 }
 
+/// The type of a stream in ffmpeg's log, e.g. "Video" for `Stream #0:1(eng): Video: h264 ...`.
+/// Stream mapping lines (`Stream #0:0 -> #0:0 (copy)`) aren't streams of their own.
+pub fn parse_ffmpeg_stream(line: &str) -> Option<&str> {
+    let line = line.trim_start().strip_prefix("Stream #")?;
+    if line.contains(" -> ") {
+        return None;
+    }
+    line.split(": ").nth(1).map(str::trim)
+}
+
 pub fn parse_ffmpeg_duration(line: &str) -> Option<f64> {
     let time_str = line.split("Duration: ").nth(1)?.split(',').next()?;
     if time_str.contains("N/A") {
@@ -126,3 +407,130 @@ pub fn parse_ffmpeg_time(line: &str) -> Option<f64> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Vec<LinkInfo> {
+        parse_playlist(content, None).links
+    }
+
+    fn headers(link: &LinkInfo) -> Vec<(&str, &str)> {
+        link.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+    }
+
+    #[test]
+    fn options_before_the_first_entry_apply_to_the_whole_playlist() {
+        let links = parse(
+            "#EXTM3U\n#EXTVLCOPT:http-referrer=https://a.example/\n#EXTVLCOPT:http-user-agent=UA\n\
+             #EXTINF:-1,One\nhttps://cdn/1.m3u8\n#EXTINF:-1,Two\nhttps://cdn/2.m3u8\n",
+        );
+        assert_eq!(links.len(), 2);
+        for link in &links {
+            assert_eq!(link.referer.as_deref(), Some("https://a.example/"));
+            assert_eq!(headers(link), [("User-Agent", "UA")]);
+        }
+    }
+
+    #[test]
+    fn groups_replace_each_others_options_but_keep_playlist_defaults() {
+        let links = parse(
+            "#EXTM3U\n#EXTVLCOPT:http-user-agent=UA\n\
+             #EXTGRP:1080p\n#EXTVLCOPT:http-referrer=https://a.example/\n#EXTINF:-1,One\nhttps://a/1.m3u8\n\
+             #EXTGRP:720p\n#EXTINF:-1,One\nhttps://b/1.m3u8\n",
+        );
+        assert_eq!(links[0].referer.as_deref(), Some("https://a.example/"));
+        assert_eq!(links[0].group.as_deref(), Some("1080p"));
+        // The second group sets no referer, so it must not inherit the first group's
+        assert_eq!(links[1].referer, None);
+        assert_eq!(links[1].group.as_deref(), Some("720p"));
+        assert_eq!(headers(&links[1]), [("User-Agent", "UA")]);
+    }
+
+    #[test]
+    fn options_between_entries_start_an_unnamed_group() {
+        // Layout written by older versions of the userscript, one referer per quality
+        let links = parse(
+            "#EXTM3U\n#EXTVLCOPT:http-referrer=https://a.example/\n#EXTINF:-1,One\nhttps://a/1\n#EXTINF:-1,Two\nhttps://a/2\n\
+             #EXTVLCOPT:http-referrer=https://b.example/\n#EXTINF:-1,One\nhttps://b/1\n#EXTINF:-1,Two\nhttps://b/2\n",
+        );
+        let referers: Vec<_> = links.iter().map(|l| l.referer.as_deref().unwrap()).collect();
+        assert_eq!(referers, ["https://a.example/", "https://a.example/", "https://b.example/", "https://b.example/"]);
+    }
+
+    #[test]
+    fn options_inside_an_entry_apply_to_that_entry_only() {
+        let links = parse(
+            "#EXTM3U\n#EXTVLCOPT:http-referrer=https://a.example/\n\
+             #EXTINF:-1,One\n#EXTVLCOPT:http-referrer=https://b.example/\n#EXTVLCOPT:http-cookie=x=1\nhttps://b/1\n\
+             #EXTINF:-1,Two\nhttps://a/2\n",
+        );
+        assert_eq!(links[0].referer.as_deref(), Some("https://b.example/"));
+        assert_eq!(headers(&links[0]), [("Cookie", "x=1")]);
+        assert_eq!(links[1].referer.as_deref(), Some("https://a.example/"));
+        assert!(links[1].headers.is_empty());
+    }
+
+    #[test]
+    fn narrower_scopes_override_headers_by_name() {
+        let links = parse(
+            "#EXTM3U\n#EXTHTTP:{\"User-Agent\":\"A\",\"cookie\":\"c=1\"}\n\
+             #EXTINF:-1,One\n#EXTVLCOPT:http-header=user-agent: B\nhttps://a/1\n",
+        );
+        assert_eq!(headers(&links[0]), [("cookie", "c=1"), ("user-agent", "B")]);
+    }
+
+    #[test]
+    fn extinf_titles_keep_commas_and_attributes() {
+        let info = parse_extinf(r#"1440.5 tvg-name="A, B" tvg-logo="http://x/y.png" group-title="Show",Show, The - Episode 3 [1080p]"#);
+        assert_eq!(info.duration, Some(1440.5));
+        assert_eq!(info.attribute("tvg-name"), Some("A, B"));
+        assert_eq!(info.attribute("group-title"), Some("Show"));
+        assert_eq!(info.title, "Show, The - Episode 3 [1080p]");
+        assert_eq!(parse_extinf("-1,Plain").duration, None);
+    }
+
+    #[test]
+    fn tolerates_bom_crlf_blank_lines_and_tags_before_the_url() {
+        let playlist = parse_playlist(
+            "\u{feff}#EXTM3U\r\n\r\n#EXTINF:-1,One\r\n\r\n#EXT-X-FOO:1\r\n#EXT-X-MEDIA:TYPE=SUBTITLES,NAME=\"English\",URI=\"subs/1.vtt\"\r\n\
+             #EXT-X-MEDIA:TYPE=AUDIO,NAME=\"English\",URI=\"dub/1.m3u8\"\r\n#EXTVLCOPT:network-caching=1000\r\nhttps://cdn/1.m3u8\r\n",
+            None,
+        );
+        assert_eq!(playlist.links.len(), 1);
+        assert_eq!(playlist.links[0].name, "One");
+        assert_eq!(playlist.links[0].url, "https://cdn/1.m3u8");
+        assert_eq!(playlist.links[0].subtitles.len(), 1);
+        assert_eq!(playlist.warnings, [ParseWarning { line: 5, message: "unknown tag #EXT-X-FOO, ignored".to_string() }]);
+    }
+
+    #[test]
+    fn resolves_relative_uris_against_the_playlist() {
+        let content = "#EXTM3U\n#EXT-X-MEDIA:TYPE=SUBTITLES,NAME=\"English\",URI=\"../subs/1.vtt\"\n#EXTINF:-1,One\nvideo/1.m3u8\n";
+        let remote = parse_playlist(content, Some("https://host/show/list.m3u")).links;
+        assert_eq!(remote[0].url, "https://host/show/video/1.m3u8");
+        assert_eq!(remote[0].subtitles[0].url, "https://host/subs/1.vtt");
+
+        let local = parse_playlist(content, Some("lists/show.m3u")).links;
+        assert_eq!(Path::new(&local[0].url), Path::new("lists/video/1.m3u8"));
+    }
+
+    #[test]
+    fn reports_entries_without_urls_and_urls_without_entries() {
+        let playlist = parse_playlist("#EXTM3U\n#EXTINF:-1,One\n#EXTINF:-1,Two\nhttps://cdn/2.m3u8\nhttps://cdn/Three.mp4?token=1\n", None);
+        let names: Vec<_> = playlist.links.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Two", "Three"]);
+        let lines: Vec<_> = playlist.warnings.iter().map(|w| w.line).collect();
+        assert_eq!(lines, [2, 5]);
+    }
+
+    #[test]
+    fn ffmpeg_stream_lines_give_their_type() {
+        assert_eq!(parse_ffmpeg_stream("  Stream #0:0(eng): Video: h264 (High), yuv420p, 1920x1080"), Some("Video"));
+        assert_eq!(parse_ffmpeg_stream("  Stream #0:1[0x101](jpn): Audio: aac (LC), 48000 Hz"), Some("Audio"));
+        assert_eq!(parse_ffmpeg_stream("  Stream #0:2: Subtitle: ass"), Some("Subtitle"));
+        assert_eq!(parse_ffmpeg_stream("  Stream #0:0 -> #0:0 (copy)"), None);
+        assert_eq!(parse_ffmpeg_stream("  Duration: 00:23:40.02, start: 0.000000"), None);
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{bail, Result};
+use futures_util::{stream, StreamExt};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use crate::dash;
+use crate::direct;
+use crate::downloader::add_input;
+use crate::ffmpeg::ffprobe_path;
+use crate::hls;
+use crate::http;
+use crate::types::{LinkInfo, Settings};
+use crate::utils::url_extension;
+use crate::verify::StreamCounts;
+
+/// Links probed at the same time.
+const CONCURRENT_PROBES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamKind {
+    HlsMaster,
+    HlsMedia,
+    Dash,
+    Progressive,
+    Unknown,
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StreamKind::HlsMaster => "HLS master",
+            StreamKind::HlsMedia => "HLS media",
+            StreamKind::Dash => "DASH",
+            StreamKind::Progressive => "Progressive",
+            StreamKind::Unknown => "Unknown",
+        })
+    }
+}
+
+/// What ffprobe reports about a file or stream.
+#[derive(Debug, Clone, Default)]
+pub struct MediaInfo {
+    pub duration: Option<f64>,
+    pub resolution: Option<(u32, u32)>,
+    pub streams: StreamCounts,
+    /// Container tags with lowercase keys, Matroska stores them uppercase.
+    pub tags: HashMap<String, String>,
+}
+
+/// What a link looks like before downloading it.
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub content_type: Option<String>,
+    pub kind: StreamKind,
+    pub duration: Option<f64>,
+    pub resolution: Option<(u32, u32)>,
+    /// Content-Length for progressive files, bandwidth × duration for streams.
+    pub size: Option<u64>,
+}
+
+impl Probe {
+    fn failed(status: Option<u16>, error: String) -> Self {
+        Probe { status, error: Some(error), content_type: None, kind: StreamKind::Unknown, duration: None, resolution: None, size: None }
+    }
+
+    pub fn reachable(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs ffprobe on a local file, or on a URL with the link's headers.
+pub async fn ffprobe(settings: &Settings, input: &str, link_info: Option<&LinkInfo>) -> Result<MediaInfo> {
+    let mut cmd = Command::new(ffprobe_path(&settings.ffmpeg_path));
+    cmd.args(["-v", "error", "-print_format", "json", "-show_format", "-show_streams"]);
+    match link_info {
+        Some(link_info) => add_input(&mut cmd, input, link_info),
+        None => {
+            cmd.arg("-i").arg(input);
+        }
+    }
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+
+    let Ok(output) = tokio::time::timeout(Duration::from_secs(settings.timeout), cmd.output()).await else {
+        bail!("ffprobe timed out after {} seconds", settings.timeout);
+    };
+    let output = output?;
+    if !output.status.success() {
+        bail!("ffprobe failed: {}", String::from_utf8_lossy(&output.stderr).lines().last().unwrap_or_default());
+    }
+    let json: Value = serde_json::from_slice(&output.stdout)?;
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    let count = |kind: &str| streams.iter().filter(|s| s["codec_type"] == kind).count();
+    let video = streams.iter().find(|s| s["codec_type"] == "video");
+    Ok(MediaInfo {
+        duration: json["format"]["duration"].as_str().and_then(|d| d.parse().ok()),
+        resolution: video.and_then(|v| Some((v["width"].as_u64()? as u32, v["height"].as_u64()? as u32))),
+        streams: StreamCounts { video: count("video"), audio: count("audio"), subtitles: count("subtitle") },
+        tags: json["format"]["tags"].as_object()
+            .map(|tags| tags.iter().filter_map(|(k, v)| Some((k.to_lowercase(), v.as_str()?.to_string()))).collect())
+            .unwrap_or_default(),
+    })
+}
+
+fn stream_kind(url: &str, content_type: Option<&str>) -> StreamKind {
+    let content_type = content_type.unwrap_or_default().to_lowercase();
+    if url_extension(url).as_deref() == Some("m3u8") || content_type.contains("mpegurl") {
+        StreamKind::HlsMedia
+    } else if dash::is_dash(url) || content_type.contains("dash+xml") {
+        StreamKind::Dash
+    } else if direct::is_direct(url) || content_type.starts_with("video/") || content_type.contains("matroska") {
+        StreamKind::Progressive
+    } else {
+        StreamKind::Unknown
+    }
+}
+
+/// Requests a link with its headers and reads what it can without downloading the video:
+/// playlists and manifests are read in full, progressive files only up to their headers
+/// before ffprobe has a look.
+pub async fn probe(client: &Client, link_info: &LinkInfo, settings: &Settings) -> Probe {
+    let resp = match http::with_headers(client.get(&link_info.url), link_info).send().await {
+        Ok(resp) => resp,
+        Err(e) => return Probe::failed(None, e.to_string()),
+    };
+    let status = resp.status();
+    if !status.is_success() {
+        return Probe::failed(Some(status.as_u16()), status.canonical_reason().unwrap_or("HTTP error").to_string());
+    }
+    let content_type = resp.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(String::from);
+    let mut probe = Probe {
+        status: Some(status.as_u16()),
+        error: None,
+        kind: stream_kind(&link_info.url, content_type.as_deref()),
+        content_type,
+        duration: link_info.duration,
+        resolution: None,
+        size: None,
+    };
+
+    match probe.kind {
+        StreamKind::HlsMedia | StreamKind::Dash => {
+            let content = match resp.text().await {
+                Ok(content) => content,
+                Err(e) => return Probe { error: Some(e.to_string()), ..probe },
+            };
+            let bandwidth = if probe.kind == StreamKind::Dash {
+                probe.duration = dash::presentation_duration(&content).or(probe.duration);
+                let representations = dash::parse_manifest(&content, &link_info.url).unwrap_or_default();
+                let selection = dash::select(&representations);
+                let video = selection.video.and_then(|i| representations.iter().filter(|r| r.kind == dash::ContentKind::Video).nth(i));
+                probe.resolution = video.and_then(|v| v.resolution);
+                video.map(|v| v.bandwidth)
+            } else if let Some(variant) = hls::parse_master(&content, &link_info.url).as_ref().and_then(hls::best_variant) {
+                probe.kind = StreamKind::HlsMaster;
+                probe.resolution = variant.resolution;
+                if let Ok(media) = http::get_text(client, &variant.uri, link_info, 1).await {
+                    probe.duration = hls::media_duration(&media).or(probe.duration);
+                }
+                Some(variant.bandwidth)
+            } else {
+                probe.duration = hls::media_duration(&content).or(probe.duration);
+                None
+            };
+            probe.size = bandwidth.filter(|&b| b > 0).zip(probe.duration).map(|(b, d)| (b as f64 * d / 8.0) as u64);
+        }
+        StreamKind::Progressive | StreamKind::Unknown => {
+            probe.size = resp.content_length();
+            // The body isn't needed, dropping the response closes the connection
+            drop(resp);
+            if let Ok(info) = ffprobe(settings, &link_info.url, Some(link_info)).await {
+                probe.duration = info.duration.or(probe.duration);
+                probe.resolution = info.resolution;
+            }
+        }
+        StreamKind::HlsMaster => {}
+    }
+    probe
+}
+
+/// Probes every link, a few at a time, keeping their order.
+pub async fn probe_all(links: &[LinkInfo], settings: &Settings) -> Result<Vec<Probe>> {
+    let client = http::client(settings)?;
+    Ok(stream::iter(links)
+        .map(|link| probe(&client, link, settings))
+        .buffered(CONCURRENT_PROBES)
+        .collect()
+        .await)
+}
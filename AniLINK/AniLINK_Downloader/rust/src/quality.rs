@@ -0,0 +1,124 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashSet;
+use crate::types::{LinkInfo, QualityPreference};
+
+lazy_static! {
+    static ref RES_RE: Regex = Regex::new(r"(\d{3,4})p").unwrap();
+}
+
+pub struct EpisodeGroup<'a> {
+    pub name: &'a str,
+    pub links: Vec<&'a LinkInfo>,
+}
+
+/// Groups entries sharing an episode name, keeping playlist order for both groups and sources.
+pub fn group_by_episode(links: &[LinkInfo]) -> Vec<EpisodeGroup<'_>> {
+    let mut groups: Vec<EpisodeGroup> = Vec::new();
+    for link in links {
+        match groups.iter_mut().find(|g| g.name == link.name) {
+            Some(group) => group.links.push(link),
+            None => groups.push(EpisodeGroup { name: &link.name, links: vec![link] }),
+        }
+    }
+    groups
+}
+
+pub fn quality_label(quality: &str) -> &str {
+    quality.trim().trim_start_matches('[').trim_end_matches(']').trim()
+}
+
+/// Ranks a quality tag by vertical resolution so that "best" can be picked between sources.
+/// Tags without a recognizable resolution rank lowest.
+pub fn quality_rank(quality: Option<&str>) -> u32 {
+    let Some(quality) = quality else { return 0 };
+    let label = quality_label(quality).to_lowercase();
+    if let Some(res) = RES_RE.captures(&label).and_then(|c| c[1].parse().ok()) {
+        return res;
+    }
+    if label.contains("4k") || label.contains("uhd") {
+        2160
+    } else if label.contains("fhd") || label.contains("full hd") {
+        1080
+    } else if label.contains("hd") {
+        720
+    } else if label.contains("sd") {
+        480
+    } else {
+        0
+    }
+}
+
+fn matches_quality(link: &LinkInfo, wanted: &str) -> bool {
+    link.quality.as_deref().is_some_and(|q| quality_label(q).eq_ignore_ascii_case(quality_label(wanted)))
+}
+
+fn best_of<'a>(links: &[&'a LinkInfo]) -> &'a LinkInfo {
+    // max_by_key returns the last maximum, so iterate in reverse to prefer the earliest source on ties
+    links.iter().rev().max_by_key(|l| quality_rank(l.quality.as_deref())).unwrap()
+}
+
+/// The source tagged with the wanted quality, else the best one not above its resolution,
+/// else the best one there is.
+fn closest<'a>(links: &[&'a LinkInfo], wanted: &str) -> &'a LinkInfo {
+    if let Some(link) = links.iter().find(|l| matches_quality(l, wanted)) {
+        return link;
+    }
+    let height = quality_rank(Some(wanted));
+    let lower: Vec<&LinkInfo> = links.iter().copied()
+        .filter(|l| (1..=height).contains(&quality_rank(l.quality.as_deref())))
+        .collect();
+    if lower.is_empty() { best_of(links) } else { best_of(&lower) }
+}
+
+/// Picks one source per episode according to the preference. Episodes that don't offer a
+/// specific quality get the nearest lower one, or their best source when there's none.
+/// Returned links keep playlist order.
+pub fn select_links(links: &[LinkInfo], preference: &QualityPreference) -> Vec<LinkInfo> {
+    if *preference == QualityPreference::All {
+        return links.to_vec();
+    }
+
+    let chosen: HashSet<usize> = group_by_episode(links)
+        .iter()
+        .map(|group| match preference {
+            QualityPreference::Specific(wanted) => closest(&group.links, wanted).id,
+            _ => best_of(&group.links).id,
+        })
+        .collect();
+
+    links.iter().filter(|l| chosen.contains(&l.id)).cloned().collect()
+}
+
+pub fn has_multiple_sources(links: &[LinkInfo]) -> bool {
+    group_by_episode(links).iter().any(|g| g.links.len() > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::new_link;
+
+    fn playlist(names: &[&str]) -> Vec<LinkInfo> {
+        names.iter().enumerate().map(|(id, name)| new_link(id, name, format!("https://example.com/{}.m3u8", id))).collect()
+    }
+
+    #[test]
+    fn one_source_is_picked_per_episode() {
+        let links = playlist(&["Ep 1 [720p]", "Ep 1 [1080p]", "Ep 1 [360p]", "Ep 2 [480p]", "Ep 2 [720p]", "Ep 3 [Sub]"]);
+        let cases = [
+            ("best", QualityPreference::Best, vec![1, 4, 5]),
+            ("all", QualityPreference::All, vec![0, 1, 2, 3, 4, 5]),
+            ("exact", QualityPreference::Specific("720p".to_string()), vec![0, 4, 5]),
+            ("exact in brackets", QualityPreference::Specific("[1080p]".to_string()), vec![1, 4, 5]),
+            ("nearest lower", QualityPreference::Specific("1440p".to_string()), vec![1, 4, 5]),
+            ("nearest lower per episode", QualityPreference::Specific("600p".to_string()), vec![2, 3, 5]),
+            ("nothing lower", QualityPreference::Specific("240p".to_string()), vec![1, 4, 5]),
+            ("missing quality", QualityPreference::Specific("Dub".to_string()), vec![1, 4, 5]),
+        ];
+        for (name, preference, expected) in cases {
+            let ids: Vec<usize> = select_links(&links, &preference).iter().map(|l| l.id).collect();
+            assert_eq!(ids, expected, "{}", name);
+        }
+    }
+}
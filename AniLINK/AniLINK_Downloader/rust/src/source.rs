@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Context, Result};
+use console::{style, Term};
+use ini::Ini;
+use regex::Regex;
+use sanitize_filename::sanitize;
+use std::fs;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
+use crate::http;
+use crate::formats::{self, INPUT_EXTENSIONS};
+use crate::parser::{name_from_uri, Playlist};
+use crate::types::Settings;
+
+/// Line that ends a pasted playlist.
+const PASTE_END: &str = "END";
+
+/// Where the playlist is read from.
+#[derive(Debug, Clone)]
+pub enum PlaylistSource {
+    File(PathBuf),
+    Url(String),
+    Stdin,
+    Pasted(String),
+}
+
+impl PlaylistSource {
+    /// Interprets a path, an http(s) URL or `-` for stdin.
+    pub fn from_input(input: &str) -> Self {
+        let input = input.trim().trim_matches('"').trim_matches('\'');
+        if input == "-" {
+            PlaylistSource::Stdin
+        } else if input.starts_with("http://") || input.starts_with("https://") {
+            PlaylistSource::Url(input.to_string())
+        } else if let Some(rest) = input.strip_prefix('~') {
+            PlaylistSource::File(dirs_next::home_dir().map(|h| h.join(rest.trim_start_matches(['/', '\\']))).unwrap_or_else(|| input.into()))
+        } else {
+            PlaylistSource::File(input.into())
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            PlaylistSource::File(path) => path.display().to_string(),
+            PlaylistSource::Url(url) => url.clone(),
+            PlaylistSource::Stdin => "stdin".to_string(),
+            PlaylistSource::Pasted(_) => "pasted playlist".to_string(),
+        }
+    }
+
+    /// Name the playlist would have on disk, used when it has no title of its own.
+    fn basename(&self) -> Option<String> {
+        match self {
+            PlaylistSource::File(path) => path.file_stem().map(|s| s.to_string_lossy().to_string()),
+            PlaylistSource::Url(url) => name_from_uri(url),
+            PlaylistSource::Stdin | PlaylistSource::Pasted(_) => None,
+        }
+    }
+}
+
+fn is_playlist_file(path: &Path) -> bool {
+    path.is_file() && path.extension().and_then(|e| e.to_str()).is_some_and(|e| INPUT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Files in `dir` whose name matches a `*`/`?` wildcard pattern, sorted by name.
+fn matching_files(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let pattern = format!("(?i)^{}$", regex::escape(pattern).replace(r"\*", ".*").replace(r"\?", "."));
+    let re = Regex::new(&pattern)?;
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.file_name().is_some_and(|n| re.is_match(&n.to_string_lossy())))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Expands one input into playlist sources. A folder yields every playlist file in it and a
+/// file name with `*` or `?` is matched against its folder.
+pub fn expand(input: &str) -> Result<Vec<PlaylistSource>> {
+    let source = PlaylistSource::from_input(input);
+    let PlaylistSource::File(path) = &source else { return Ok(vec![source]) };
+    let files = if path.is_dir() {
+        matching_files(path, "*")?.into_iter().filter(|p| is_playlist_file(p)).collect()
+    } else {
+        match path.file_name().map(|n| n.to_string_lossy().to_string()) {
+            Some(name) if name.contains(['*', '?']) => {
+                let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                matching_files(dir, &name)?
+            }
+            _ => return Ok(vec![source]),
+        }
+    };
+    if files.is_empty() {
+        return Err(anyhow!("No playlists found for '{}'", input));
+    }
+    Ok(files.into_iter().map(PlaylistSource::File).collect())
+}
+
+/// Settings overrides for a playlist file, read from `<playlist name>.ini` next to it.
+pub fn overrides(source: &PlaylistSource) -> Result<Option<(PathBuf, Ini)>> {
+    let PlaylistSource::File(path) = source else { return Ok(None) };
+    let ini_path = path.with_extension("ini");
+    if !ini_path.is_file() {
+        return Ok(None);
+    }
+    let conf = Ini::load_from_file(&ini_path).with_context(|| format!("Failed to read {}", ini_path.display()))?;
+    Ok(Some((ini_path, conf)))
+}
+
+fn read_until_end(reader: impl BufRead) -> Result<String> {
+    let mut content = String::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim() == PASTE_END {
+            break;
+        }
+        content.push_str(&line);
+        content.push('\n');
+    }
+    Ok(content)
+}
+
+/// Reads a playlist pasted into the terminal until end of input or a line reading `END`, so
+/// blank lines between entries are kept.
+pub fn read_pasted(term: &Term) -> Result<PlaylistSource> {
+    let eof_key = if cfg!(windows) { "Ctrl+Z then Enter" } else { "Ctrl+D" };
+    term.write_line(&format!("{}", style(format!("Paste the playlist, then type {} on a line of its own or press {}:", PASTE_END, eof_key)).dim()))?;
+    let content = read_until_end(std::io::stdin().lock())?;
+    if content.trim().is_empty() {
+        return Err(anyhow!("Nothing was pasted"));
+    }
+    Ok(PlaylistSource::Pasted(content))
+}
+
+/// Loads and parses a playlist. Remote playlists are fetched with the given referer and their
+/// final URL is used to resolve relative entries.
+pub async fn load(source: &PlaylistSource, settings: &Settings, referer: Option<&str>) -> Result<Playlist> {
+    let (content, base) = match source {
+        PlaylistSource::File(path) => {
+            let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            (content, Some(path.to_string_lossy().to_string()))
+        }
+        PlaylistSource::Url(url) => {
+            let mut request = http::client(settings)?.get(url);
+            if let Some(referer) = referer {
+                request = request.header("Referer", referer);
+            }
+            let resp = request.send().await?.error_for_status().with_context(|| format!("Failed to fetch {}", url))?;
+            let base = resp.url().to_string();
+            (resp.text().await?, Some(base))
+        }
+        PlaylistSource::Stdin => {
+            let mut content = String::new();
+            std::io::stdin().read_to_string(&mut content).context("Failed to read the playlist from stdin")?;
+            (content, None)
+        }
+        PlaylistSource::Pasted(content) => (content.clone(), None),
+    };
+    Ok(formats::parse(&content, base.as_deref()))
+}
+
+/// Suggested output folder: the playlist's title, else the file or URL name.
+pub fn default_folder(source: &PlaylistSource, playlist: &Playlist) -> String {
+    playlist.title.clone()
+        .or_else(|| source.basename())
+        .map(|name| sanitize(name.trim()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "AniLINK Downloads".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pasted_playlists_keep_blank_lines_until_the_end_marker() {
+        let pasted = "#EXTM3U\n\n#EXTINF:-1,One\nhttps://cdn/1.m3u8\n\n#EXTINF:-1,Two\nhttps://cdn/2.m3u8\nEND\nignored\n";
+        assert_eq!(read_until_end(pasted.as_bytes()).unwrap(), "#EXTM3U\n\n#EXTINF:-1,One\nhttps://cdn/1.m3u8\n\n#EXTINF:-1,Two\nhttps://cdn/2.m3u8\n");
+        assert_eq!(read_until_end("a\n\nb".as_bytes()).unwrap(), "a\n\nb\n");
+    }
+}
@@ -0,0 +1,376 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::lang;
+use crate::types::{Subtitle, SubtitleFormat};
+use crate::utils::url_extension;
+
+lazy_static! {
+    static ref TIMING_RE: Regex = Regex::new(r"^\s*((?:\d+:)?\d{1,2}:\d{2}[.,]\d{1,3})\s*-->\s*((?:\d+:)?\d{1,2}:\d{2}[.,]\d{1,3})").unwrap();
+    static ref VTT_TAG_RE: Regex = Regex::new(r"</?(?:c|v|lang|ruby|rt)(?:[.\s][^>]*)?>|<\d{2}:[\d:.]+>").unwrap();
+    static ref ASS_TAG_RE: Regex = Regex::new(r"\{[^}]*\}").unwrap();
+    static ref ASS_DRAWING_RE: Regex = Regex::new(r"\{[^}]*\\p[1-9]").unwrap();
+}
+
+/// Event fields of an ASS script without a Format line of its own.
+const ASS_EVENT_FIELDS: &str = "Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+impl FromStr for SubtitleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "vtt" | "webvtt" => Ok(SubtitleFormat::Vtt),
+            "srt" => Ok(SubtitleFormat::Srt),
+            "ass" | "ssa" => Ok(SubtitleFormat::Ass),
+            other => Err(format!("unknown subtitle format '{}' (expected vtt, srt or ass)", other)),
+        }
+    }
+}
+
+impl fmt::Display for SubtitleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl SubtitleFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Ass => "ass",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+/// Guesses the format from the content first, since subtitle URLs often lack a usable extension.
+pub fn detect_format(url: &str, content: &str) -> SubtitleFormat {
+    let head = content.trim_start_matches('\u{feff}').trim_start();
+    if head.starts_with("WEBVTT") {
+        SubtitleFormat::Vtt
+    } else if head.starts_with("[Script Info]") {
+        SubtitleFormat::Ass
+    } else if url_extension(url).is_none_or(|e| e != "vtt") && head.lines().take(20).any(|l| TIMING_RE.is_match(l)) {
+        SubtitleFormat::Srt
+    } else {
+        url_extension(url).and_then(|e| e.parse().ok()).unwrap_or(SubtitleFormat::Vtt)
+    }
+}
+
+fn parse_timestamp(ts: &str) -> Option<u64> {
+    let ts = ts.replace(',', ".");
+    let (clock, frac) = ts.split_once('.')?;
+    let parts: Vec<u64> = clock.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let (h, m, s) = match parts.as_slice() {
+        [m, s] => (0, *m, *s),
+        [h, m, s] => (*h, *m, *s),
+        _ => return None,
+    };
+    let ms: u64 = format!("{:0<3}", &frac[..frac.len().min(3)]).parse().ok()?;
+    Some(((h * 60 + m) * 60 + s) * 1000 + ms)
+}
+
+/// Reads the cues of a WebVTT or SRT file, also counting cues whose timing can't be used.
+/// Header, NOTE, STYLE and REGION blocks are skipped.
+fn parse_blocks(content: &str) -> (Vec<Cue>, usize) {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut cues = Vec::new();
+    let mut malformed = 0;
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| !TIMING_RE.is_match(l));
+        let timing = lines.next().and_then(|l| TIMING_RE.captures(l));
+        let times = timing.as_ref().and_then(|t| Some((parse_timestamp(&t[1])?, parse_timestamp(&t[2])?)));
+        match times {
+            Some((start_ms, end_ms)) if end_ms >= start_ms => {
+                let text = lines.collect::<Vec<_>>().join("\n");
+                cues.push(Cue { start_ms, end_ms, text });
+            }
+            _ if block.contains("-->") => malformed += 1,
+            _ => {}
+        }
+    }
+    (cues, malformed)
+}
+
+pub fn parse_cues(content: &str) -> Vec<Cue> {
+    parse_blocks(content).0
+}
+
+/// Reads the dialogue of an ASS script as plain cues. Italics, bold and underline are kept as
+/// tags, other styling and positioning is dropped.
+fn parse_ass(content: &str) -> Vec<Cue> {
+    let format_fields = |format: &str| format.split(',').map(|f| f.trim().to_lowercase()).collect::<Vec<_>>();
+    let mut fields = format_fields(ASS_EVENT_FIELDS);
+    let mut in_events = false;
+    let mut cues = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+        } else if let (true, Some(format)) = (in_events, line.strip_prefix("Format:")) {
+            fields = format_fields(format);
+        } else if let (true, Some(dialogue)) = (in_events, line.strip_prefix("Dialogue:")) {
+            // Text is the last field and may contain commas itself
+            let values: Vec<&str> = dialogue.splitn(fields.len(), ',').collect();
+            let field = |name: &str| fields.iter().position(|f| f == name).and_then(|i| values.get(i)).map(|v| v.trim());
+            let (Some(start_ms), Some(end_ms), Some(text)) = (field("start").and_then(parse_timestamp), field("end").and_then(parse_timestamp), field("text")) else {
+                continue;
+            };
+            // Vector drawings, typically signs, have no text to show
+            if ASS_DRAWING_RE.is_match(text) {
+                continue;
+            }
+            let text = text
+                .replace("{\\i1}", "<i>").replace("{\\i0}", "</i>")
+                .replace("{\\b1}", "<b>").replace("{\\b0}", "</b>")
+                .replace("{\\u1}", "<u>").replace("{\\u0}", "</u>");
+            let text = ASS_TAG_RE.replace_all(&text, "").replace("\\N", "\n").replace("\\n", "\n").replace("\\h", " ");
+            if end_ms >= start_ms && !text.trim().is_empty() {
+                cues.push(Cue { start_ms, end_ms, text: text.trim().to_string() });
+            }
+        }
+    }
+    cues.sort_by_key(|c| c.start_ms);
+    cues
+}
+
+fn format_srt_time(ms: u64) -> String {
+    format!("{:02}:{:02}:{:02},{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+fn format_ass_time(ms: u64) -> String {
+    format!("{}:{:02}:{:02}.{:02}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000 / 10)
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let body = cues.iter()
+        .map(|cue| format!("{} --> {}\n{}\n", format_srt_time(cue.start_ms).replace(',', "."), format_srt_time(cue.end_ms).replace(',', "."), cue.text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("WEBVTT\n\n{}", body)
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    cues.iter()
+        .enumerate()
+        .map(|(i, cue)| format!(
+            "{}\n{} --> {}\n{}\n",
+            i + 1,
+            format_srt_time(cue.start_ms),
+            format_srt_time(cue.end_ms),
+            VTT_TAG_RE.replace_all(&cue.text, "")
+        ))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn to_ass(cues: &[Cue]) -> String {
+    let mut out = String::from(
+        "[Script Info]\nScriptType: v4.00+\nPlayResX: 1920\nPlayResY: 1080\nScaledBorderAndShadow: yes\n\n\
+         [V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Default,Arial,64,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,3,1,2,60,60,50,1\n\n\
+         [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+    for cue in cues {
+        let text = VTT_TAG_RE.replace_all(&cue.text, "")
+            .replace("<i>", "{\\i1}").replace("</i>", "{\\i0}")
+            .replace("<b>", "{\\b1}").replace("</b>", "{\\b0}")
+            .replace("<u>", "{\\u1}").replace("</u>", "{\\u0}")
+            .replace('\n', "\\N");
+        out += &format!("Dialogue: 0,{},{},Default,,0,0,0,,{}\n", format_ass_time(cue.start_ms), format_ass_time(cue.end_ms), text);
+    }
+    out
+}
+
+/// Converts subtitle text to the wanted format. ASS sources keep only italics, bold and
+/// underline when converted to SRT or WebVTT.
+pub fn convert(content: &str, from: SubtitleFormat, to: SubtitleFormat) -> String {
+    if from == to {
+        return content.to_string();
+    }
+    let cues = if from == SubtitleFormat::Ass { parse_ass(content) } else { parse_cues(content) };
+    match to {
+        SubtitleFormat::Srt => to_srt(&cues),
+        SubtitleFormat::Ass => to_ass(&cues),
+        SubtitleFormat::Vtt => to_vtt(&cues),
+    }
+}
+
+pub fn language_tag(sub: &Subtitle) -> String {
+    lang::detect(&sub.name, sub.language.as_deref()).map(|l| l.ietf()).unwrap_or_else(|| "und".to_string())
+}
+
+/// Sidecar path next to the video, e.g. `Episode 1.en.srt`. Tracks sharing a language get a number.
+pub fn sidecar_path(output_file: &Path, sub: &Subtitle, format: SubtitleFormat, taken: &[PathBuf]) -> PathBuf {
+    let stem = output_file.file_stem().unwrap_or_default().to_string_lossy();
+    let lang = language_tag(sub);
+    let mut path = output_file.with_file_name(format!("{}.{}.{}", stem, lang, format.extension()));
+    let mut n = 2;
+    while taken.contains(&path) {
+        path = output_file.with_file_name(format!("{}.{}.{}.{}", stem, lang, n, format.extension()));
+        n += 1;
+    }
+    path
+}
+
+pub struct CheckedSubtitle {
+    pub format: SubtitleFormat,
+    pub content: String,
+    pub repairs: Vec<String>,
+}
+
+/// Fixes the decimal separator of each timing line and drops cues whose timing can't be read.
+/// Everything else stays as it was: cue identifiers and settings, headers, STYLE, NOTE and
+/// REGION blocks.
+fn repair_blocks(content: &str, wrong_separator: char) -> String {
+    let separator = if wrong_separator == ',' { "." } else { "," };
+    content.split("\n\n")
+        .filter(|block| parse_blocks(block).1 == 0)
+        .map(|block| {
+            block.split('\n')
+                .map(|line| match TIMING_RE.captures(line) {
+                    Some(t) => format!(
+                        "{} --> {}{}",
+                        t[1].replace(wrong_separator, separator),
+                        t[2].replace(wrong_separator, separator),
+                        &line[t.get(0).unwrap().end()..]
+                    ),
+                    None => line.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Checks that a fetched subtitle is something ffmpeg can mux and applies simple repairs:
+/// BOM and CRLF removal, WebVTT/SRT decimal separators and dropping cues with broken timing.
+pub fn validate(url: &str, content_type: Option<&str>, body: &str) -> Result<CheckedSubtitle, String> {
+    let mut repairs = Vec::new();
+    let mut content = body.replace("\r\n", "\n");
+    if let Some(stripped) = content.strip_prefix('\u{feff}') {
+        content = stripped.to_string();
+        repairs.push("stripped BOM".to_string());
+    }
+    if content.trim().is_empty() {
+        return Err("empty file".to_string());
+    }
+    let head = content.trim_start().chars().take(64).collect::<String>().to_lowercase();
+    if content_type.is_some_and(|t| t.contains("text/html")) || head.starts_with("<!doctype") || head.starts_with("<html") {
+        return Err("server returned an HTML page".to_string());
+    }
+
+    let format = detect_format(url, &content);
+    if format == SubtitleFormat::Ass {
+        if !content.lines().any(|l| l.trim_start().starts_with("Dialogue:")) {
+            return Err("no dialogue lines".to_string());
+        }
+        return Ok(CheckedSubtitle { format, content, repairs });
+    }
+
+    let (cues, malformed) = parse_blocks(&content);
+    if cues.is_empty() {
+        return Err("no readable cues".to_string());
+    }
+    let wrong_separator = if format == SubtitleFormat::Vtt { ',' } else { '.' };
+    let bad_separators = content.lines()
+        .filter_map(|l| TIMING_RE.captures(l))
+        .any(|t| t[1].contains(wrong_separator) || t[2].contains(wrong_separator));
+    if bad_separators {
+        repairs.push("fixed decimal separators".to_string());
+    }
+    if malformed > 0 {
+        repairs.push(format!("dropped {} malformed cue(s)", malformed));
+    }
+    let missing_header = format == SubtitleFormat::Vtt && !content.trim_start().starts_with("WEBVTT");
+    if missing_header {
+        repairs.push("added WEBVTT header".to_string());
+    }
+    if bad_separators || malformed > 0 {
+        content = repair_blocks(&content, wrong_separator);
+    }
+    if missing_header {
+        content = format!("WEBVTT\n\n{}", content.trim_start());
+    }
+    Ok(CheckedSubtitle { format, content, repairs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ass_converts_to_plain_cues() {
+        let ass = "[Script Info]\nTitle: x\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 0,0:00:03.50,0:00:05.00,Default,,0,0,0,,{\\an8}Second, with a comma\n\
+                   Comment: 0,0:00:00.00,0:00:09.00,Default,,0,0,0,,not shown\n\
+                   Dialogue: 0,0:00:01.00,0:00:02.25,Default,,0,0,0,,{\\i1}First{\\i0}\\Nline\n\
+                   Dialogue: 0,0:00:01.00,0:00:09.00,Sign,,0,0,0,,{\\p1}m 0 0 l 10 10{\\p0}\n";
+        assert_eq!(
+            convert(ass, SubtitleFormat::Ass, SubtitleFormat::Srt),
+            "1\n00:00:01,000 --> 00:00:02,250\n<i>First</i>\nline\n\n2\n00:00:03,500 --> 00:00:05,000\nSecond, with a comma\n"
+        );
+        assert!(convert(ass, SubtitleFormat::Ass, SubtitleFormat::Vtt).starts_with("WEBVTT\n\n00:00:01.000 --> 00:00:02.250\n"));
+        assert_eq!(convert(ass, SubtitleFormat::Ass, SubtitleFormat::Ass), ass);
+    }
+
+    #[test]
+    fn valid_subtitles_pass_unchanged() {
+        let vtt = "WEBVTT\n\nSTYLE\n::cue { color: yellow }\n\n1\n00:00:01.000 --> 00:00:02.000 line:10%\nHello\n";
+        let checked = validate("https://cdn/sub.vtt", None, vtt).unwrap();
+        assert_eq!((checked.format, checked.content.as_str()), (SubtitleFormat::Vtt, vtt));
+        assert!(checked.repairs.is_empty());
+
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nHello\n";
+        let checked = validate("https://cdn/sub", Some("text/plain"), srt).unwrap();
+        assert_eq!((checked.format, checked.content.as_str()), (SubtitleFormat::Srt, srt));
+
+        let ass = "[Script Info]\n\n[Events]\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Hello\n";
+        assert_eq!(validate("https://cdn/sub.ass", None, ass).unwrap().format, SubtitleFormat::Ass);
+    }
+
+    #[test]
+    fn broken_subtitles_are_rejected() {
+        let cases = [
+            ("empty", None, " \n", "empty file"),
+            ("html page", Some("text/html; charset=utf-8"), "WEBVTT\n", "server returned an HTML page"),
+            ("html body", None, "<!DOCTYPE html><html></html>", "server returned an HTML page"),
+            ("no cues", None, "WEBVTT\n\nNOTE nothing here\n", "no readable cues"),
+            ("ass without dialogue", None, "[Script Info]\nTitle: x\n", "no dialogue lines"),
+        ];
+        for (name, content_type, body, error) in cases {
+            assert_eq!(validate("https://cdn/sub.vtt", content_type, body).err().as_deref(), Some(error), "{}", name);
+        }
+    }
+
+    #[test]
+    fn repairs_keep_cue_settings_and_style_blocks() {
+        let vtt = "\u{feff}WEBVTT\r\n\r\nSTYLE\r\n::cue { color: yellow }\r\n\r\n\
+                   intro\r\n00:00:01,000 --> 00:00:02,500 align:start line:0\r\n<v Narrator>Hello\r\n\r\n\
+                   00:00:03.000 --> 00:00:0x.000\r\nBroken\r\n\r\n\
+                   00:00:04.000 --> 00:00:05.000\r\nBye\r\n";
+        let checked = validate("https://cdn/sub.vtt", None, vtt).unwrap();
+        assert_eq!(
+            checked.content,
+            "WEBVTT\n\nSTYLE\n::cue { color: yellow }\n\nintro\n00:00:01.000 --> 00:00:02.500 align:start line:0\n<v Narrator>Hello\n\n00:00:04.000 --> 00:00:05.000\nBye\n"
+        );
+        assert_eq!(checked.repairs, ["stripped BOM", "fixed decimal separators", "dropped 1 malformed cue(s)"]);
+
+        let headless = validate("https://cdn/sub.vtt", None, "00:00:01.000 --> 00:00:02.000 position:10%\nHi\n").unwrap();
+        assert_eq!(headless.content, "WEBVTT\n\n00:00:01.000 --> 00:00:02.000 position:10%\nHi\n");
+        assert_eq!(headless.repairs, ["added WEBVTT header"]);
+
+        let srt = validate("https://cdn/sub.srt", None, "1\n00:00:01.000 --> 00:00:02.000\nHi\n").unwrap();
+        assert_eq!((srt.format, srt.content.as_str()), (SubtitleFormat::Srt, "1\n00:00:01,000 --> 00:00:02,000\nHi\n"));
+    }
+}
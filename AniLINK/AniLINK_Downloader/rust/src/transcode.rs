@@ -0,0 +1,80 @@
+use crate::types::{Settings, TranscodeProfile};
+
+pub const COPY_PROFILE: &str = "copy";
+
+/// Software-only profiles offered when the config doesn't define any, so they work without a GPU.
+pub fn builtin_profiles() -> Vec<TranscodeProfile> {
+    vec![
+        TranscodeProfile {
+            name: "h264-1080p".to_string(),
+            video_codec: "libx264".to_string(),
+            crf: Some(22),
+            video_bitrate: None,
+            max_height: Some(1080),
+            preset: Some("veryfast".to_string()),
+            audio_codec: "aac".to_string(),
+            audio_bitrate: Some("160k".to_string()),
+        },
+        TranscodeProfile {
+            name: "h264-720p".to_string(),
+            video_codec: "libx264".to_string(),
+            crf: Some(23),
+            video_bitrate: None,
+            max_height: Some(720),
+            preset: Some("veryfast".to_string()),
+            audio_codec: "aac".to_string(),
+            audio_bitrate: Some("128k".to_string()),
+        },
+        TranscodeProfile {
+            name: "hevc-small".to_string(),
+            video_codec: "libx265".to_string(),
+            crf: Some(28),
+            video_bitrate: None,
+            max_height: Some(720),
+            preset: Some("fast".to_string()),
+            audio_codec: "aac".to_string(),
+            audio_bitrate: Some("96k".to_string()),
+        },
+    ]
+}
+
+/// The profile selected for this run, or `None` when streams are copied untouched.
+pub fn active_profile(settings: &Settings) -> Option<&TranscodeProfile> {
+    let name = settings.transcode_profile.as_deref()?;
+    settings.profiles.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+pub fn copies_audio(profile: Option<&TranscodeProfile>) -> bool {
+    profile.is_none_or(|p| p.audio_codec == COPY_PROFILE)
+}
+
+/// Codec options for the output. Subtitles and anything the profile leaves alone are copied.
+pub fn ffmpeg_args(profile: Option<&TranscodeProfile>) -> Vec<String> {
+    let mut args = vec!["-c".to_string(), "copy".to_string()];
+    let Some(profile) = profile else { return args };
+
+    args.extend(["-c:v".into(), profile.video_codec.clone()]);
+    if profile.video_codec != COPY_PROFILE {
+        if let Some(crf) = profile.crf {
+            args.extend(["-crf".into(), crf.to_string()]);
+        }
+        if let Some(bitrate) = &profile.video_bitrate {
+            args.extend(["-b:v".into(), bitrate.clone()]);
+        }
+        if let Some(preset) = &profile.preset {
+            args.extend(["-preset".into(), preset.clone()]);
+        }
+        if let Some(height) = profile.max_height {
+            // Only ever scale down, keeping the width even as most encoders require
+            args.extend(["-vf".into(), format!("scale=-2:'min({},ih)'", height)]);
+        }
+    }
+
+    args.extend(["-c:a".into(), profile.audio_codec.clone()]);
+    if profile.audio_codec != COPY_PROFILE {
+        if let Some(bitrate) = &profile.audio_bitrate {
+            args.extend(["-b:a".into(), bitrate.clone()]);
+        }
+    }
+    args
+}
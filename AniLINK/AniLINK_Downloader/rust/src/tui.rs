@@ -9,7 +9,7 @@ use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Line, Span},
+    text::{Line, Span, Text},
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Frame, Terminal,
 };
@@ -17,17 +17,20 @@ use std::io;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
-use crate::types::{DownloadStatus, LinkInfo};
+use crate::types::{DownloadStatus, LinkInfo, Speed, TrackStatus};
 use crate::process::{pause_process, resume_process, kill_process};
 
 pub struct DownloadTUI {
     pub selected: Option<usize>,
     pub list_state: ListState,
     pub downloads: Arc<Mutex<Vec<(LinkInfo, DownloadStatus)>>>,
+    pub show_details: bool,
+    /// Session-wide warning shown above the list, such as low disk space.
+    pub notice: Arc<Mutex<Option<String>>>,
 }
 
 impl DownloadTUI {
-    pub fn new_with_state(downloads: Arc<Mutex<Vec<(LinkInfo, DownloadStatus)>>>) -> Self {
+    pub fn new_with_state(downloads: Arc<Mutex<Vec<(LinkInfo, DownloadStatus)>>>, notice: Arc<Mutex<Option<String>>>) -> Self {
         let mut list_state = ListState::default();
         let len = downloads.lock().len();
         if len > 0 {
@@ -37,6 +40,8 @@ impl DownloadTUI {
             selected: if len == 0 { None } else { Some(0) },
             list_state,
             downloads,
+            show_details: false,
+            notice,
         }
     }
 
@@ -66,7 +71,7 @@ impl DownloadTUI {
         if let Some(selected) = self.selected {
             let mut downloads = self.downloads.lock();
             if selected < downloads.len() {
-                let (link_info, _) = &mut downloads[selected];
+                let (link_info, status) = &mut downloads[selected];
                 let was_paused = link_info.paused.load(Ordering::SeqCst);
                 
                 if let Some(pid) = *link_info.process_id.lock() {
@@ -77,6 +82,9 @@ impl DownloadTUI {
                         let _ = pause_process(pid);
                         link_info.paused.store(true, Ordering::SeqCst);
                     }
+                } else if matches!(status, DownloadStatus::Downloading { .. }) {
+                    // Direct downloads run without ffmpeg and watch the flag themselves
+                    link_info.paused.store(!was_paused, Ordering::SeqCst);
                 }
             }
         }
@@ -86,7 +94,7 @@ impl DownloadTUI {
         let mut downloads = self.downloads.lock();
         let any_paused = downloads.iter().any(|(li, _)| li.paused.load(Ordering::SeqCst));
         
-        for (link_info, _) in downloads.iter_mut() {
+        for (link_info, status) in downloads.iter_mut() {
             if let Some(pid) = *link_info.process_id.lock() {
                 if any_paused {
                     let _ = resume_process(pid);
@@ -95,6 +103,8 @@ impl DownloadTUI {
                     let _ = pause_process(pid);
                     link_info.paused.store(true, Ordering::SeqCst);
                 }
+            } else if matches!(status, DownloadStatus::Downloading { .. }) {
+                link_info.paused.store(!any_paused, Ordering::SeqCst);
             }
         }
     }
@@ -108,14 +118,71 @@ impl DownloadTUI {
         }
     }
 
+    pub fn toggle_details(&mut self) {
+        self.show_details = !self.show_details;
+    }
+
     pub fn draw(&mut self, f: &mut Frame) {
+        let notice = self.notice.lock().clone();
+        let notice_height = if notice.is_some() { 3 } else { 0 };
+        let details_height = if self.show_details { 10 } else { 0 };
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(0), Constraint::Length(3)])
+            .constraints([Constraint::Length(notice_height), Constraint::Min(0), Constraint::Length(details_height), Constraint::Length(3)])
             .split(f.area());
 
-        self.draw_downloads(f, chunks[0]);
-        self.draw_keybindings(f, chunks[1]);
+        if let Some(notice) = notice {
+            let para = Paragraph::new(Line::from(Span::styled(format!("⚠ {}", notice), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))))
+                .block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Yellow)).title("Warning"));
+            f.render_widget(para, chunks[0]);
+        }
+        self.draw_downloads(f, chunks[1]);
+        if self.show_details {
+            self.draw_details(f, chunks[2]);
+        }
+        self.draw_keybindings(f, chunks[3]);
+    }
+
+    fn draw_details(&self, f: &mut Frame, area: Rect) {
+        let downloads = self.downloads.lock();
+        let Some((link_info, _)) = self.selected.and_then(|i| downloads.get(i)) else { return };
+
+        let label = |text: &str| Span::styled(format!("{:<10}", text), Style::default().fg(Color::Cyan));
+        let mut lines = vec![
+            Line::from(vec![label("URL"), Span::raw(link_info.url.clone())]),
+            Line::from(vec![label("Referer"), Span::raw(link_info.referer.clone().unwrap_or_else(|| "-".to_string()))]),
+        ];
+        if !link_info.headers.is_empty() {
+            // Names only, cookie values shouldn't end up on screen
+            let names = link_info.headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ");
+            lines.push(Line::from(vec![label("Headers"), Span::raw(names)]));
+        }
+
+        let statuses = link_info.subtitle_status.lock();
+        if link_info.subtitles.is_empty() {
+            lines.push(Line::from(vec![label("Subtitles"), Span::raw("none")]));
+        }
+        for (idx, sub) in link_info.subtitles.iter().enumerate() {
+            let status = statuses.get(idx).map(|(_, s)| s);
+            let (text, color) = match status {
+                None => ("pending".to_string(), Color::Gray),
+                Some(TrackStatus::Ready { repairs }) if repairs.is_empty() => ("✓ ok".to_string(), Color::Green),
+                Some(TrackStatus::Ready { repairs }) => (format!("✓ repaired: {}", repairs.join(", ")), Color::Yellow),
+                Some(TrackStatus::Skipped { reason }) => (format!("✗ skipped: {}", reason), Color::Red),
+            };
+            lines.push(Line::from(vec![
+                label(if idx == 0 { "Subtitles" } else { "" }),
+                Span::raw(format!("{} - ", sub.name)),
+                Span::styled(text, Style::default().fg(color)),
+            ]));
+        }
+        for warning in link_info.warnings.lock().iter() {
+            lines.push(Line::from(Span::styled(format!("⚠ {}", warning), Style::default().fg(Color::Yellow))));
+        }
+
+        let para = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(format!("Details - {}", link_info.name)));
+        f.render_widget(para, area);
     }
 
     fn draw_downloads(&mut self, f: &mut Frame, area: Rect) {
@@ -123,6 +190,7 @@ impl DownloadTUI {
         let spinner_frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
         let frame_idx = (std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() / 80) as usize % spinner_frames.len();
         let spinner = spinner_frames[frame_idx];
+        let grouped = downloads.iter().any(|(li, _)| li.playlist != downloads[0].0.playlist);
 
         let items: Vec<ListItem> = downloads
             .iter()
@@ -140,17 +208,19 @@ impl DownloadTUI {
                         let filled = ((*progress / 100.0) * bar_width as f64) as usize;
                         let bar = format!("[{}{}]", "█".repeat(filled), "░".repeat(bar_width - filled));
                         (
-                            format!("{}{} {:.1}% - {:.1}MB @ {:.2}x", pause_indicator, spinner, progress, size_mb, speed),
+                            format!("{}{} {:.1}% - {:.1}MB @ {}", pause_indicator, spinner, progress, size_mb, format_speed(*speed)),
                             bar
                         )
                     }
                     DownloadStatus::Paused => ("⏸ Paused".to_string(), String::new()),
                     DownloadStatus::Completed { size_mb } => (format!("✓ {:.1}MB", size_mb), "[████████████████████]".to_string()),
+                    DownloadStatus::Incomplete { reason } => (format!("⚠ Incomplete: {}", reason), String::new()),
                     DownloadStatus::Failed { error } => (format!("✗ {}", error), String::new()),
                 };
 
                 let color = match status {
                     DownloadStatus::Completed { .. } => Color::Green,
+                    DownloadStatus::Incomplete { .. } => Color::Magenta,
                     DownloadStatus::Failed { .. } => Color::Red,
                     DownloadStatus::Starting => Color::Yellow,
                     DownloadStatus::Downloading { .. } if is_paused => Color::Yellow,
@@ -158,7 +228,7 @@ impl DownloadTUI {
                     _ => Color::Gray,
                 };
 
-                let content = if progress_bar.is_empty() {
+                let mut content = if progress_bar.is_empty() {
                     Line::from(vec![
                         Span::raw(prefix),
                         Span::styled(&link_info.name, Style::default().fg(color)),
@@ -176,7 +246,22 @@ impl DownloadTUI {
                     ])
                 };
 
-                ListItem::new(content)
+                let warning_count = link_info.warnings.lock().len();
+                if warning_count > 0 {
+                    content.spans.push(Span::styled(format!("  ⚠ {}", warning_count), Style::default().fg(Color::Yellow)));
+                }
+
+                // Entries are queued playlist by playlist, so a header marks where each one starts
+                let starts_group = idx == 0 || downloads[idx - 1].0.playlist != link_info.playlist;
+                if grouped && starts_group {
+                    let header = Line::from(Span::styled(
+                        format!("── {} ──", link_info.playlist.as_deref().unwrap_or("Playlist")),
+                        Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+                    ));
+                    ListItem::new(Text::from(vec![header, content]))
+                } else {
+                    ListItem::new(content)
+                }
             })
             .collect();
 
@@ -193,6 +278,7 @@ impl DownloadTUI {
             ("↑/↓", "Select"),
             ("Space", "Pause/Resume"),
             ("A", "Toggle All"),
+            ("D", "Details"),
             ("Shift+Q", "Exit"),
         ];
 
@@ -220,6 +306,13 @@ impl DownloadTUI {
     }
 }
 
+fn format_speed(speed: Speed) -> String {
+    match speed {
+        Speed::Realtime(multiple) => format!("{:.2}x", multiple),
+        Speed::BytesPerSec(rate) => format!("{:.1}MB/s", rate / 1_048_576.0),
+    }
+}
+
 pub fn run_tui(mut tui: DownloadTUI) -> Result<()> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -255,6 +348,7 @@ pub fn run_tui(mut tui: DownloadTUI) -> Result<()> {
                     KeyCode::Up | KeyCode::Char('k') => tui.previous(),
                     KeyCode::Char(' ') => tui.toggle_pause(),
                     KeyCode::Char('a') | KeyCode::Char('A') => tui.toggle_pause_all(),
+                    KeyCode::Char('d') | KeyCode::Char('D') => tui.toggle_details(),
                     _ => {}
                 }
             }
@@ -263,7 +357,7 @@ pub fn run_tui(mut tui: DownloadTUI) -> Result<()> {
         // Check if all downloads are complete
         let downloads = tui.downloads.lock();
         let all_done = downloads.iter().all(|(_, status)| {
-            matches!(status, DownloadStatus::Completed { .. } | DownloadStatus::Failed { .. })
+            matches!(status, DownloadStatus::Completed { .. } | DownloadStatus::Incomplete { .. } | DownloadStatus::Failed { .. })
         });
         drop(downloads);
         
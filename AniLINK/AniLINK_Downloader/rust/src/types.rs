@@ -16,6 +16,22 @@ pub struct Subtitle {
     pub name: String,
     pub url: String,
     pub default: bool,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioTrack {
+    pub name: String,
+    pub url: String,
+    pub default: bool,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub title: String,
+    pub start: f64,
+    pub end: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -24,10 +40,37 @@ pub struct LinkInfo {
     pub name: String,
     pub url: String,
     pub referer: Option<String>,
+    /// Extra request headers such as User-Agent, Origin or Cookie.
+    pub headers: Vec<(String, String)>,
     pub subtitles: Vec<Subtitle>,
+    /// Separate audio tracks muxed alongside the stream's own audio.
+    pub audio: Vec<AudioTrack>,
     pub quality: Option<String>,
+    pub show: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub chapters: Vec<Chapter>,
+    pub logo: Option<String>,
+    pub group: Option<String>,
+    /// Runtime in seconds from `#EXTINF`, used for progress when ffmpeg reports none.
+    pub duration: Option<f64>,
+    /// Runtime ffprobe measured before downloading. Unlike the often rounded `#EXTINF` one it's
+    /// exact enough to judge a finished file by.
+    pub probed_duration: Option<f64>,
+    /// Every `key="value"` attribute of the `#EXTINF` line, e.g. `tvg-id`.
+    pub attributes: Vec<(String, String)>,
+    /// Name of the playlist this entry was queued from.
+    pub playlist: Option<String>,
     pub process_id: Arc<Mutex<Option<u32>>>,
     pub paused: Arc<AtomicBool>,
+    pub warnings: Arc<Mutex<Vec<String>>>,
+    pub subtitle_status: Arc<Mutex<Vec<(String, TrackStatus)>>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TrackStatus {
+    Ready { repairs: Vec<String> },
+    Skipped { reason: String },
 }
 
 #[derive(Debug, Clone)]
@@ -37,14 +80,74 @@ pub struct Settings {
     pub speed_limit: Option<String>,
     pub timeout: u64,
     pub ffmpeg_path: String,
+    pub name_template: String,
+    pub season_folders: bool,
+    pub write_nfo: bool,
+    pub container: Container,
+    pub transcode_profile: Option<String>,
+    pub profiles: Vec<TranscodeProfile>,
+    pub sidecar_subtitles: bool,
+    pub sidecar_format: Option<SubtitleFormat>,
+    pub fonts_dir: Option<String>,
+    pub direct_downloads: bool,
+    pub connections: usize,
+    /// Per-host connection counts from the [Connections] section, e.g. `cdn.example.com = 8`
+    pub host_connections: Vec<(String, usize)>,
+    pub probe_links: bool,
+    /// Free space to keep on the output filesystem, 0 turns the guard off
+    pub min_free_space_mb: u64,
+    pub verify_downloads: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubtitleFormat {
+    Vtt,
+    Srt,
+    Ass,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodeProfile {
+    pub name: String,
+    pub video_codec: String,
+    pub crf: Option<u32>,
+    pub video_bitrate: Option<String>,
+    pub max_height: Option<u32>,
+    pub preset: Option<String>,
+    pub audio_codec: String,
+    pub audio_bitrate: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+    Mkv,
+    Mp4,
+    Ts,
+    Original,
 }
 
 #[derive(Debug, Clone)]
 pub enum DownloadStatus {
     Pending,
     Starting,
-    Downloading { progress: f64, speed: f64, size_mb: f64 },
+    Downloading { progress: f64, speed: Speed, size_mb: f64 },
     Paused,
     Completed { size_mb: f64 },
+    /// Finished, but shorter than the source or missing streams.
+    Incomplete { reason: String },
     Failed { error: String },
 }
+
+/// ffmpeg reports speed as a multiple of playback time, direct downloads in bytes per second.
+#[derive(Debug, Clone, Copy)]
+pub enum Speed {
+    Realtime(f64),
+    BytesPerSec(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QualityPreference {
+    Best,
+    All,
+    Specific(String),
+}
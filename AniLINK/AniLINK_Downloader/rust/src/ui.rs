@@ -1,11 +1,16 @@
 use anyhow::Result;
-use comfy_table::{presets::UTF8_FULL, Cell, ContentArrangement, Table};
+use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
 use console::{style, Term};
 use dialoguer::{Confirm, Input};
 use std::path::Path;
-use crate::types::{LinkInfo, Settings};
+use crate::types::{LinkInfo, QualityPreference, Settings};
 use crate::parser::parse_number_ranges;
-use crate::utils::get_output_file;
+use crate::quality::{group_by_episode, quality_label};
+use crate::transcode::COPY_PROFILE;
+use crate::utils::output_files;
+use crate::http;
+use crate::probe::Probe;
+use crate::archive::Archive;
 
 pub fn customize(term: &Term, settings: &mut Settings) -> Result<()> {
     loop {
@@ -17,6 +22,19 @@ pub fn customize(term: &Term, settings: &mut Settings) -> Result<()> {
         table.add_row(vec!["3", "Speed Limit (e.g., 500k, 2M)", settings.speed_limit.as_deref().unwrap_or("None")]);
         table.add_row(vec!["4", "Timeout (seconds)", &settings.timeout.to_string()]);
         table.add_row(vec!["5", "FFmpeg Path", &settings.ffmpeg_path]);
+        table.add_row(vec!["6", "Filename Template", &settings.name_template]);
+        table.add_row(vec!["7", "Show/Season Folders", if settings.season_folders { "Yes" } else { "No" }]);
+        table.add_row(vec!["8", "Write NFO Files", if settings.write_nfo { "Yes" } else { "No" }]);
+        table.add_row(vec!["9", "Container (mkv, mp4, ts, original)", &settings.container.to_string()]);
+        table.add_row(vec!["10", "Transcode Profile", settings.transcode_profile.as_deref().unwrap_or(COPY_PROFILE)]);
+        table.add_row(vec!["11", "Sidecar Subtitles", if settings.sidecar_subtitles { "Yes" } else { "No" }]);
+        table.add_row(vec!["12", "Sidecar Format (original, srt, ass, vtt)", &settings.sidecar_format.map(|f| f.to_string()).unwrap_or_else(|| "original".to_string())]);
+        table.add_row(vec!["13", "Fonts Directory (for ASS subtitles)", settings.fonts_dir.as_deref().unwrap_or("None")]);
+        table.add_row(vec!["14", "Direct Downloads (MP4/MKV without ffmpeg)", if settings.direct_downloads { "Yes" } else { "No" }]);
+        table.add_row(vec!["15", "Connections per Direct Download", &settings.connections.to_string()]);
+        table.add_row(vec!["16", "Probe Links Before Downloading", if settings.probe_links { "Yes" } else { "No" }]);
+        table.add_row(vec!["17", "Minimum Free Space (MB, 0 to disable)", &settings.min_free_space_mb.to_string()]);
+        table.add_row(vec!["18", "Verify Finished Files (ffprobe)", if settings.verify_downloads { "Yes" } else { "No" }]);
         term.write_line(&format!("{}", table))?;
 
         let choices: String = Input::new().with_prompt("Enter numbers to change (e.g., 1,3)").allow_empty(true).interact_text_on(term)?;
@@ -32,6 +50,56 @@ pub fn customize(term: &Term, settings: &mut Settings) -> Result<()> {
                 }
                 "4" => settings.timeout = Input::new().with_prompt("Timeout (seconds)").default(settings.timeout).interact_text_on(term)?,
                 "5" => settings.ffmpeg_path = Input::new().with_prompt("FFmpeg path ('ffmpeg' for system PATH)").default(settings.ffmpeg_path.clone()).interact_text_on(term)?,
+                "6" => {
+                    term.write_line(&format!("{}", style("Placeholders: {name} {show} {season} {episode} {quality} {group} {ext} or any EXTINF attribute like {tvg-id}, '/' creates folders, {season:02} pads numbers").dim()))?;
+                    settings.name_template = Input::new().with_prompt("Filename template").default(settings.name_template.clone()).interact_text_on(term)?;
+                }
+                "7" => settings.season_folders = Confirm::new().with_prompt("Place files under Show/Season NN/ (when the template has no folders)?").default(settings.season_folders).interact_on(term)?,
+                "8" => settings.write_nfo = Confirm::new().with_prompt("Write tvshow.nfo and episode .nfo files after downloading?").default(settings.write_nfo).interact_on(term)?,
+                "9" => {
+                    let container: String = Input::new().with_prompt("Container (mkv, mp4, ts, original)").default(settings.container.to_string()).interact_text_on(term)?;
+                    match container.parse() {
+                        Ok(container) => settings.container = container,
+                        Err(e) => term.write_line(&format!("{}", style(e).red()))?,
+                    }
+                }
+                "10" => {
+                    let names = std::iter::once(COPY_PROFILE).chain(settings.profiles.iter().map(|p| p.name.as_str())).collect::<Vec<_>>().join(", ");
+                    term.write_line(&format!("{}", style(format!("Profiles: {} (edit [Profile.<name>] sections in settings.ini to add more)", names)).dim()))?;
+                    let profile: String = Input::new().with_prompt("Transcode profile").default(settings.transcode_profile.clone().unwrap_or_else(|| COPY_PROFILE.to_string())).interact_text_on(term)?;
+                    if profile == COPY_PROFILE {
+                        settings.transcode_profile = None;
+                    } else if settings.profiles.iter().any(|p| p.name.eq_ignore_ascii_case(&profile)) {
+                        settings.transcode_profile = Some(profile);
+                    } else {
+                        term.write_line(&format!("{}", style(format!("Unknown profile '{}'", profile)).red()))?;
+                    }
+                }
+                "11" => settings.sidecar_subtitles = Confirm::new().with_prompt("Download subtitles as separate files next to the video?").default(settings.sidecar_subtitles).interact_on(term)?,
+                "12" => {
+                    let format: String = Input::new().with_prompt("Sidecar format (original, srt, ass, vtt)").default(settings.sidecar_format.map(|f| f.to_string()).unwrap_or_else(|| "original".to_string())).interact_text_on(term)?;
+                    if format.trim().eq_ignore_ascii_case("original") {
+                        settings.sidecar_format = None;
+                    } else {
+                        match format.parse() {
+                            Ok(format) => settings.sidecar_format = Some(format),
+                            Err(e) => term.write_line(&format!("{}", style(e).red()))?,
+                        }
+                    }
+                }
+                "13" => {
+                    let dir: String = Input::new().with_prompt("Fonts directory (empty to disable)").default(settings.fonts_dir.clone().unwrap_or_default()).allow_empty(true).interact_text_on(term)?;
+                    settings.fonts_dir = if dir.is_empty() { None } else { Some(dir) };
+                }
+                "14" => settings.direct_downloads = Confirm::new().with_prompt("Fetch progressive MP4/MKV files over HTTP instead of through ffmpeg?").default(settings.direct_downloads).interact_on(term)?,
+                "15" => {
+                    term.write_line(&format!("{}", style("Per-host counts go in the [Connections] section of settings.ini, e.g. cdn.example.com = 8").dim()))?;
+                    let connections: usize = Input::new().with_prompt("Connections per direct download").default(settings.connections).interact_text_on(term)?;
+                    settings.connections = connections.max(1);
+                }
+                "16" => settings.probe_links = Confirm::new().with_prompt("Check every link (reachability, type, duration, size) before downloading?").default(settings.probe_links).interact_on(term)?,
+                "17" => settings.min_free_space_mb = Input::new().with_prompt("Minimum free space in MB (new downloads wait below it)").default(settings.min_free_space_mb).interact_text_on(term)?,
+                "18" => settings.verify_downloads = Confirm::new().with_prompt("Check finished files with ffprobe and retry truncated ones?").default(settings.verify_downloads).interact_on(term)?,
                 _ => {}
             }
         }
@@ -41,13 +109,15 @@ pub fn customize(term: &Term, settings: &mut Settings) -> Result<()> {
     Ok(())
 }
 
-pub fn check_existing(term: &Term, links: &[LinkInfo], folder: &Path) -> Result<Vec<LinkInfo>> {
+/// `all_links` is the whole playlist, which output names are worked out against.
+pub fn check_existing(term: &Term, links: &[LinkInfo], all_links: &[LinkInfo], folder: &Path, settings: &Settings) -> Result<Vec<LinkInfo>> {
+    let outputs = output_files(all_links, folder, settings);
     let mut existing = Vec::new();
     for (idx, link) in links.iter().enumerate() {
-        let output = get_output_file(link, folder, links);
+        let output = &outputs[&link.id];
         if output.exists() {
             let size = output.metadata()?.len() as f64 / 1_048_576.0;
-            existing.push((idx + 1, output.file_name().unwrap().to_string_lossy().to_string(), size));
+            existing.push((idx + 1, output.strip_prefix(folder).unwrap_or(output).to_string_lossy().to_string(), size));
         }
     }
 
@@ -64,10 +134,132 @@ pub fn check_existing(term: &Term, links: &[LinkInfo], folder: &Path) -> Result<
         let overwrite = parse_number_ranges(&choices);
 
         return Ok(links.iter().enumerate()
-            .filter(|(idx, link)| overwrite.contains(&(idx + 1)) || !get_output_file(link, folder, links).exists())
+            .filter(|(idx, link)| overwrite.contains(&(idx + 1)) || !outputs[&link.id].exists())
             .map(|(_, link)| link.clone())
             .collect());
     }
 
     Ok(links.to_vec())
 }
+
+/// Leaves out entries the archive says were downloaded before, unless they're picked again.
+pub fn skip_archived(term: &Term, links: &[LinkInfo], archive: &Archive) -> Result<Vec<LinkInfo>> {
+    let archived: Vec<_> = links.iter().enumerate().filter_map(|(idx, link)| Some((idx, archive.find(link)?))).collect();
+    if archived.is_empty() {
+        return Ok(links.to_vec());
+    }
+
+    term.write_line(&format!("\n{}", style("These entries were downloaded before according to the archive:").bold().yellow()))?;
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL).set_header(vec!["No.", "Episode", "Saved As", "Date"]).set_content_arrangement(ContentArrangement::Dynamic);
+    for (idx, entry) in &archived {
+        table.add_row(vec![Cell::new(idx + 1), Cell::new(&links[*idx].name), Cell::new(entry.path.display()), Cell::new(&entry.date)]);
+    }
+    term.write_line(&format!("{}", table))?;
+
+    let choices: String = Input::new().with_prompt("Select entries to download again (e.g., 1-3,5)").allow_empty(true).interact_text_on(term)?;
+    let again = parse_number_ranges(&choices);
+    Ok(links.iter().enumerate()
+        .filter(|(idx, _)| again.contains(&(idx + 1)) || !archived.iter().any(|(i, _)| i == idx))
+        .map(|(_, link)| link.clone())
+        .collect())
+}
+
+/// Headers whose values are credentials.
+const SECRET_HEADERS: [&str; 3] = ["Cookie", "Authorization", "Proxy-Authorization"];
+
+/// Lists what a run would do: each entry's source, output file and the headers sent with it.
+pub fn print_dry_run(term: &Term, links: &[LinkInfo], folder: &Path, settings: &Settings) -> Result<()> {
+    term.write_line(&format!("\n{}", style(format!("Dry run, {} entries:", links.len())).bold()))?;
+    let outputs = output_files(links, folder, settings);
+    for (idx, link) in links.iter().enumerate() {
+        let output = &outputs[&link.id];
+        term.write_line(&format!("\n{} {}", style(format!("{:>3}.", idx + 1)).dim(), style(&link.name).bold()))?;
+        term.write_line(&format!("     {} {}", style("URL    ").cyan(), link.url))?;
+        term.write_line(&format!("     {} {}", style("Output ").cyan(), output.display()))?;
+        let headers = http::request_headers(link);
+        if headers.is_empty() {
+            term.write_line(&format!("     {} {}", style("Headers").cyan(), style("none").dim()))?;
+        }
+        for (i, (name, value)) in headers.iter().enumerate() {
+            let label = if i == 0 { "Headers" } else { "       " };
+            // Credentials shouldn't end up in terminal scrollback or pasted logs
+            let value = if SECRET_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+                style(format!("<hidden, {} characters>", value.chars().count())).dim().to_string()
+            } else {
+                value.clone()
+            };
+            term.write_line(&format!("     {} {}: {}", style(label).cyan(), name, value))?;
+        }
+    }
+    Ok(())
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Shows what probing found for each entry.
+pub fn print_probes(term: &Term, links: &[LinkInfo], probes: &[Probe]) -> Result<()> {
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL).set_header(vec!["No.", "Episode", "Status", "Type", "Duration", "Resolution", "Size"]).set_content_arrangement(ContentArrangement::Dynamic);
+    for (idx, (link, probe)) in links.iter().zip(probes).enumerate() {
+        let status = match (&probe.error, probe.status) {
+            (Some(error), Some(code)) => Cell::new(format!("✗ {} {}", code, error)).fg(Color::Red),
+            (Some(error), None) => Cell::new(format!("✗ {}", error)).fg(Color::Red),
+            (None, code) => Cell::new(format!("✓ {}", code.unwrap_or(200))).fg(Color::Green),
+        };
+        let kind = match &probe.content_type {
+            Some(content_type) if probe.reachable() => format!("{} ({})", probe.kind, content_type.split(';').next().unwrap_or_default()),
+            _ => probe.kind.to_string(),
+        };
+        table.add_row(vec![
+            Cell::new(idx + 1),
+            Cell::new(&link.name),
+            status,
+            Cell::new(kind),
+            Cell::new(probe.duration.map(format_duration).unwrap_or_else(|| "?".to_string())),
+            Cell::new(probe.resolution.map(|(w, h)| format!("{}x{}", w, h)).unwrap_or_else(|| "?".to_string())),
+            Cell::new(probe.size.map(|s| format!("{:.1} MB", s as f64 / 1_048_576.0)).unwrap_or_else(|| "?".to_string())),
+        ]);
+    }
+    term.write_line(&format!("{}", table))?;
+    Ok(())
+}
+
+/// Asks which probed entries to download, by default the reachable ones.
+pub fn select_probed(term: &Term, links: &[LinkInfo], probes: &[Probe]) -> Result<Vec<LinkInfo>> {
+    let unreachable = probes.iter().filter(|p| !p.reachable()).count();
+    if unreachable > 0 {
+        term.write_line(&format!("{}", style(format!("{} of {} entries can't be reached.", unreachable, links.len())).bold().yellow()))?;
+    }
+    let choices: String = Input::new().with_prompt("Select entries to download (e.g., 1-3,5, empty for all reachable)").allow_empty(true).interact_text_on(term)?;
+    let selected = parse_number_ranges(&choices);
+    Ok(links.iter().zip(probes).enumerate()
+        .filter(|(idx, (_, probe))| if selected.is_empty() { probe.reachable() } else { selected.contains(&(idx + 1)) })
+        .map(|(_, (link, _))| link.clone())
+        .collect())
+}
+
+pub fn select_quality(term: &Term, links: &[LinkInfo]) -> Result<QualityPreference> {
+    term.write_line(&format!("\n{}", style("Some episodes are available in several qualities:").bold().yellow()))?;
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL).set_header(vec!["No.", "Episode", "Qualities"]).set_content_arrangement(ContentArrangement::Dynamic);
+    for (idx, group) in group_by_episode(links).iter().enumerate() {
+        let qualities = group.links.iter()
+            .map(|l| l.quality.as_deref().map(quality_label).unwrap_or("?"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        table.add_row(vec![Cell::new(idx + 1), Cell::new(group.name), Cell::new(qualities)]);
+    }
+    term.write_line(&format!("{}", table))?;
+
+    loop {
+        let choice: String = Input::new().with_prompt("Quality to download ('best', 'all' or e.g. 1080p)").default("best".to_string()).interact_text_on(term)?;
+        match choice.parse() {
+            Ok(preference) => return Ok(preference),
+            Err(e) => term.write_line(&format!("{}", style(e).red()))?,
+        }
+    }
+}
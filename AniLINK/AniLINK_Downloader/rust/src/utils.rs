@@ -1,27 +1,50 @@
 use sanitize_filename::sanitize;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use crate::types::LinkInfo;
+use crate::container::output_extension;
+use crate::naming::{layout_template, render_template};
+use crate::types::{LinkInfo, Settings};
 
-pub fn get_output_file(link_info: &LinkInfo, folder: &Path, all_links: &[LinkInfo]) -> PathBuf {
-    let mut name_without_ext = link_info.name.clone();
+/// Works out every entry's output path in one pass. An entry that renders to the same file as
+/// an earlier one gets its quality tag appended.
+pub fn output_files(all_links: &[LinkInfo], folder: &Path, settings: &Settings) -> HashMap<usize, PathBuf> {
+    let mut taken = HashSet::new();
+    all_links.iter().map(|link_info| {
+        let ext = output_extension(settings.container, &link_info.url);
+        let (template, _) = layout_template(&settings.name_template, settings.season_folders, link_info);
+        let mut parts = render_template(&template, link_info, ext);
+        if parts.is_empty() {
+            parts.push(format!("{}.{}", sanitize(&link_info.name), ext));
+        }
 
-    // Smart quality tagging
-    if let Some(quality) = &link_info.quality {
-        let base_name = &link_info.name;
-        let duplicates: Vec<_> = all_links
-            .iter()
-            .filter(|l| l.name == *base_name)
-            .map(|l| l.id)
-            .collect();
-        if duplicates.len() > 1 {
-            if let Some(pos) = duplicates.iter().position(|&id| id == link_info.id) {
-                if pos > 0 {
-                    name_without_ext = format!("{} {}", name_without_ext, quality);
-                }
-            }
+        // Smart quality tagging
+        let duplicate = !taken.insert(parts.clone());
+        if let (true, Some(quality)) = (duplicate, &link_info.quality) {
+            let file_name = parts.pop().unwrap();
+            let suffix = format!(".{}", ext);
+            let stem = file_name.strip_suffix(&suffix).unwrap_or(&file_name);
+            let ext_part = if file_name.ends_with(&suffix) { suffix.as_str() } else { "" };
+            parts.push(sanitize(format!("{} {}{}", stem, quality, ext_part)));
         }
-    }
 
-    let sanitized_name = sanitize(&name_without_ext);
-    folder.join(format!("{}.mkv", sanitized_name))
+        (link_info.id, parts.iter().fold(folder.to_path_buf(), |path, part| path.join(part)))
+    }).collect()
+}
+
+pub fn url_extension(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.rsplit_once('.').map(|(_, ext)| ext.to_lowercase())
+}
+
+/// Reads a speed limit like "500k" or "2M" as bytes per second.
+pub fn parse_rate(limit: &str) -> Option<u64> {
+    let limit = limit.trim();
+    let (number, multiplier) = match limit.char_indices().last()? {
+        (i, 'k' | 'K') => (&limit[..i], 1024),
+        (i, 'm' | 'M') => (&limit[..i], 1024 * 1024),
+        _ => (limit, 1),
+    };
+    let rate = number.trim().parse::<f64>().ok()? * multiplier as f64;
+    (rate >= 1.0).then_some(rate as u64)
 }
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use std::path::Path;
+use crate::probe;
+use crate::types::Settings;
+
+/// Shortest gap between the source and the file that counts as truncation, and the same as
+/// a share of the source duration for long episodes.
+const MIN_SHORTFALL_SECS: f64 = 2.0;
+const MAX_SHORTFALL_RATIO: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StreamCounts {
+    pub video: usize,
+    pub audio: usize,
+    pub subtitles: usize,
+}
+
+impl StreamCounts {
+    /// Counts a stream by the type ffmpeg's log gives it, ignoring attachments and data.
+    pub fn add(&mut self, kind: &str) {
+        match kind {
+            "Video" => self.video += 1,
+            "Audio" => self.audio += 1,
+            "Subtitle" => self.subtitles += 1,
+            _ => {}
+        }
+    }
+}
+
+/// Checks a finished file with ffprobe against the source duration and the streams ffmpeg
+/// said it would write. Returns why the file looks incomplete, or an error when it couldn't
+/// be checked at all.
+pub async fn verify(settings: &Settings, file: &Path, expected_duration: Option<f64>, expected_streams: Option<StreamCounts>) -> Result<Option<String>> {
+    let info = probe::ffprobe(settings, &file.to_string_lossy(), None).await?;
+    let mut problems = Vec::new();
+
+    if let Some(expected) = expected_duration.filter(|&d| d > 0.0) {
+        let actual = info.duration.unwrap_or(0.0);
+        if expected - actual > MIN_SHORTFALL_SECS.max(expected * MAX_SHORTFALL_RATIO) {
+            problems.push(format!("{:.0}s of {:.0}s", actual, expected));
+        }
+    }
+    if let Some(expected) = expected_streams {
+        let actual = info.streams;
+        for (kind, expected, actual) in [("video", expected.video, actual.video), ("audio", expected.audio, actual.audio), ("subtitle", expected.subtitles, actual.subtitles)] {
+            if actual < expected {
+                problems.push(format!("{} of {} {} streams", actual, expected, kind));
+            }
+        }
+    }
+    Ok((!problems.is_empty()).then(|| problems.join(", ")))
+}
@@ -1,7 +1,7 @@
 // ==UserScript==
 // @name        AniLINK - Episode Link Extractor
 // @namespace   https://greasyfork.org/en/users/781076-jery-js
-// @version     6.33.0
+// @version     6.33.2
 // @description Stream or download your favorite anime series effortlessly with AniLINK! Unlock the power to play any anime series directly in your preferred video player or download entire seasons in a single click using popular download managers like IDM. AniLINK generates direct download links for all episodes, conveniently sorted by quality. Elevate your anime-watching experience now!
 // @icon        https://upload-os-bbs.hoyolab.com/upload/2024/06/03/136787680/795963af96e199b14106441a955376fa_6229706912856146042.jpg
 // @author      Jery
@@ -1768,14 +1768,18 @@ async function extractEpisodes() {
             const epNums = items.map(i => i.querySelector('[data-epnum]').dataset.epnum);
             const episodes = (window._anilink_episodes || []).filter(ep => ep.links[quality] && epNums.includes(ep.number));
             const referer = episodes[0]?.links[quality]?.referer;
-            if (referer && !out.includes(referer)) out += `#EXTVLCOPT:http-referrer=${referer}\n`;
+            // Each quality is its own group so its referer doesn't leak into the next one
+            out += `#EXTGRP:${quality}\n`;
+            if (referer) out += `#EXTVLCOPT:http-referrer=${referer}\n`;
             episodes.forEach(ep => {
                 const link = ep.links[quality];
                 if (link?.tracks?.length) link.tracks.forEach(t => {
                     const type = t.kind?.startsWith('audio') ? 'AUDIO' : /^(caption|subtitle)s?/.test(t.kind) ? 'SUBTITLES' : null;
                     if (type) out += `#EXT-X-MEDIA:TYPE=${type},GROUP-ID="${type.toLowerCase()}${ep.number}",NAME="${t.label || type}",DEFAULT=${t.default ? 'YES' : 'NO'},URI="${t.file}"\n`;
                 });
-                out += `#EXTINF:-1,${ep.filename.replaceAll('/', '|')}${SRC_IN_FN ? ` [${quality}]` : ''}\n${link.stream}\n`;
+                // #EXTGRP names the quality, group-title gives the downloader's {group} the show instead
+                const groupTitle = ep.animeTitle ? ` group-title="${ep.animeTitle.replaceAll('"', "'")}"` : '';
+                out += `#EXTINF:-1${groupTitle},${ep.filename.replaceAll('/', '|')}${SRC_IN_FN ? ` [${quality}]` : ''}\n${link.stream}\n`;
             });
         }
         return out;